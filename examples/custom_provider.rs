@@ -0,0 +1,34 @@
+use anyhow::Result;
+use gitload::{
+    provider::{async_trait, Client, Provider, RequestBuilder},
+    DownloaderBuilder, Node, Remote,
+};
+
+// serves a fixed list of files from a plain http mirror
+struct Mirror;
+
+#[async_trait]
+impl Provider for Mirror {
+    async fn tree(&self, _client: &Client, _remote: &Remote) -> Result<Vec<Node>> {
+        Ok(vec![Node::tree("nvim"), Node::blob("nvim/init.lua")])
+    }
+
+    fn raw(&self, client: &Client, remote: &Remote, path: &str) -> RequestBuilder {
+        let url = format!(
+            "https://mirror.example.com/{}/{}/{}/{path}",
+            remote.user(),
+            remote.repo(),
+            remote.branch()
+        );
+        client.get(url)
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let downloader = DownloaderBuilder::new("levinion", "dotfiles", "nvim")
+        .provider(Mirror)
+        .build()?;
+    downloader.download().await?;
+    Ok(())
+}
@@ -0,0 +1,27 @@
+use anyhow::Result;
+use gitload::{
+    auth::{cached_token, DeviceFlow},
+    DownloaderBuilder,
+};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let token = match cached_token() {
+        Some(token) => token,
+        None => {
+            DeviceFlow::new("<client id>")
+                .login(|code| {
+                    println!(
+                        "open {} and enter the code {}",
+                        code.verification_uri, code.user_code
+                    );
+                })
+                .await?
+        }
+    };
+    let downloader = DownloaderBuilder::new("levinion", "dotfiles", "nvim")
+        .token(&token)
+        .build()?;
+    downloader.download().await?;
+    Ok(())
+}
@@ -12,6 +12,7 @@ async fn main() -> Result<()> {
                 process.percent() * 100.
             );
         })
-        .build();
-    downloader.download().await
+        .build()?;
+    downloader.download().await?;
+    Ok(())
 }
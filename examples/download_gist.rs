@@ -0,0 +1,19 @@
+use anyhow::Result;
+use gitload::{provider::Gist, DownloaderBuilder};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let downloader = DownloaderBuilder::gist("aa5a315d61ae9438b18d")
+        .provider(Gist::default().files(&["README.md"]))
+        .on_process(|process| {
+            println!(
+                "process: {}/{}\t{:.0}%",
+                process.current,
+                process.all,
+                process.percent() * 100.
+            );
+        })
+        .build()?;
+    downloader.download().await?;
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use anyhow::Result;
+use gitload::DownloaderBuilder;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let downloader = DownloaderBuilder::new("gitlab-org", "gitlab-runner", "docs/install")
+        .gitlab("https://gitlab.com")
+        .on_process(|process| {
+            println!(
+                "process: {}/{}\t{:.0}%",
+                process.current,
+                process.all,
+                process.percent() * 100.
+            );
+        })
+        .build()?;
+    downloader.download().await?;
+    Ok(())
+}
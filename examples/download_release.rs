@@ -0,0 +1,19 @@
+use anyhow::Result;
+use gitload::{provider::Release, DownloaderBuilder};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let release = Release::latest().assets("*x86_64-unknown-linux*");
+    let downloader = DownloaderBuilder::release("BurntSushi", "ripgrep", release)
+        .on_process(|process| {
+            println!(
+                "process: {}/{}\t{:.0}%",
+                process.current,
+                process.all,
+                process.percent() * 100.
+            );
+        })
+        .build()?;
+    downloader.download().await?;
+    Ok(())
+}
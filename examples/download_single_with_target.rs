@@ -13,6 +13,7 @@ async fn main() -> Result<()> {
                 process.percent() * 100.
             );
         })
-        .build();
-    downloader.download().await
+        .build()?;
+    downloader.download().await?;
+    Ok(())
 }
@@ -0,0 +1,9 @@
+use anyhow::Result;
+use gitload::Downloader;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let downloader = Downloader::from_spec("levinion/dotfiles/nvim#main")?;
+    downloader.download().await?;
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use anyhow::Result;
+use futures_util::StreamExt;
+use gitload::{DownloadEvent, DownloaderBuilder};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let downloader = DownloaderBuilder::new("levinion", "dotfiles", "nvim").build()?;
+    let mut events = Box::pin(downloader.stream());
+    while let Some(event) = events.next().await {
+        match event {
+            DownloadEvent::FileCompleted { entry, bytes, .. } => {
+                println!("{}: {bytes} bytes", entry.path)
+            }
+            DownloadEvent::Failed { error } => return Err(anyhow::anyhow!("{error:#}")),
+            _ => {}
+        }
+    }
+    Ok(())
+}
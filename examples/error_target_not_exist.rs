@@ -5,6 +5,7 @@ use gitload::DownloaderBuilder;
 async fn main() -> Result<()> {
     let downloader = DownloaderBuilder::new("levinion", "dotfiles", "nvim-dotfile")
         .local_path("./src")
-        .build();
-    downloader.download().await
+        .build()?;
+    downloader.download().await?;
+    Ok(())
 }
@@ -0,0 +1,68 @@
+use std::{
+    fs::{create_dir_all, rename},
+    io::Read,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::Result;
+use flate2::read::GzDecoder;
+use tar::EntryType;
+
+use crate::{part_path, Entry};
+
+// unpacks the wanted entries of a tar.gz below remote_path into local_path, the same way
+// single files are placed, on_file is called with every file, the other entries are read past
+pub(crate) fn extract(
+    archive: impl Read,
+    remote_path: &str,
+    local_path: &Path,
+    wanted: impl Fn(&str) -> bool,
+    mut on_file: impl FnMut(Entry),
+) -> Result<()> {
+    let src = PathBuf::from(remote_path);
+    let mut archive = tar::Archive::new(GzDecoder::new(archive));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !matches!(
+            entry.header().entry_type(),
+            EntryType::Regular | EntryType::Symlink
+        ) {
+            continue;
+        }
+        // the first component is the archive's top directory, such as user-repo-sha
+        let path: PathBuf = entry.path()?.components().skip(1).collect();
+        // entries climbing out of the destination are never written
+        if path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            continue;
+        }
+        let Ok(rest) = path.strip_prefix(&src) else {
+            continue;
+        };
+        if !wanted(&path.to_string_lossy()) {
+            continue;
+        }
+        // a single file is written to local_path itself
+        let dst = match rest.as_os_str().is_empty() {
+            true => local_path.to_path_buf(),
+            false => local_path.join(rest),
+        };
+        if let Some(dir) = dst.parent() {
+            create_dir_all(dir)?;
+        }
+        // unpacked aside and moved into place once complete
+        let part = part_path(&dst);
+        entry.unpack(&part)?;
+        rename(&part, &dst)?;
+        let header = entry.header();
+        on_file(Entry::unpacked(
+            path.to_string_lossy().into_owned(),
+            entry.size(),
+            header.entry_type() == EntryType::Symlink,
+            header.mode().unwrap_or_default(),
+        ));
+    }
+    Ok(())
+}
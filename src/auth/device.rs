@@ -0,0 +1,153 @@
+use std::{fs, path::PathBuf, time::Duration};
+
+use anyhow::{anyhow, Result};
+use reqwest::Client;
+
+/// The code the user enters at `verification_uri` to authorize the login
+#[derive(serde::Deserialize, Debug)]
+pub struct DeviceCode {
+    device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub expires_in: u64,
+    interval: u64,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Poll {
+    access_token: Option<String>,
+    error: Option<String>,
+}
+
+/// GitHub's OAuth device authorization flow, for interactive logins without a PAT
+pub struct DeviceFlow {
+    client_id: String,
+    scope: String,
+    host: String,
+    client: Option<Client>,
+}
+
+impl DeviceFlow {
+    const GRANT_TYPE: &'static str = "urn:ietf:params:oauth:grant-type:device_code";
+
+    /// `client_id` of the OAuth app with device flow enabled
+    pub fn new(client_id: &str) -> Self {
+        Self {
+            client_id: client_id.into(),
+            scope: "repo".into(),
+            host: "https://github.com".into(),
+            client: None,
+        }
+    }
+
+    pub fn scope(mut self, scope: &str) -> Self {
+        self.scope = scope.into();
+        self
+    }
+
+    /// Web host of a GitHub Enterprise Server, such as `https://github.mycorp.com`
+    pub fn host(mut self, host: &str) -> Self {
+        self.host = crate::provider::base_url(host);
+        self
+    }
+
+    /// The client the flow is run with, such as the one given to the downloader
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Runs the flow, `prompt` shows the code to the user, and caches the granted token,
+    /// in the platform secret store with the `keyring` feature
+    pub async fn login(&self, prompt: impl FnOnce(&DeviceCode)) -> Result<String> {
+        let client = match &self.client {
+            Some(client) => client.clone(),
+            None => crate::Downloader::default_client().build()?,
+        };
+        let res = client
+            .post(format!("{}/login/device/code", self.host))
+            .header("Accept", "application/json")
+            .form(&[("client_id", &self.client_id), ("scope", &self.scope)])
+            .send()
+            .await?
+            .text()
+            .await?;
+        let code: DeviceCode = serde_json::from_str(&res)
+            .map_err(|_| anyhow!("Is device flow enabled for the app?"))?;
+        prompt(&code);
+
+        let mut interval = code.interval;
+        loop {
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+            let res = client
+                .post(format!("{}/login/oauth/access_token", self.host))
+                .header("Accept", "application/json")
+                .form(&[
+                    ("client_id", self.client_id.as_str()),
+                    ("device_code", code.device_code.as_str()),
+                    ("grant_type", Self::GRANT_TYPE),
+                ])
+                .send()
+                .await?
+                .text()
+                .await?;
+            let poll: Poll = serde_json::from_str(&res)?;
+            if let Some(token) = poll.access_token {
+                let host = crate::provider::host_of(&self.host)
+                    .ok_or_else(|| anyhow!("No host in {}", self.host))?;
+                store_token(&host, &token)?;
+                return Ok(token);
+            }
+            match poll.error.as_deref() {
+                Some("authorization_pending") => {}
+                // github asks to back off by five more seconds
+                Some("slow_down") => interval += 5,
+                Some(err) => return Err(anyhow!("Device login failed: {err}")),
+                None => return Err(anyhow!("Device login failed")),
+            }
+        }
+    }
+}
+
+// github.com keeps the file it always had, enterprise servers get one of their own
+fn token_path(host: &str) -> Option<PathBuf> {
+    let name = match host {
+        "github.com" => "token".into(),
+        host => format!("token-{host}"),
+    };
+    Some(super::config_dir()?.join("gitload").join(name))
+}
+
+#[cfg(feature = "keyring")]
+fn store_token(host: &str, token: &str) -> Result<()> {
+    super::keyring::store_token(host, token)
+}
+
+#[cfg(not(feature = "keyring"))]
+fn store_token(host: &str, token: &str) -> Result<()> {
+    let path = token_path(host).ok_or_else(|| anyhow!("No config directory to cache the token"))?;
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(&path, token)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+/// The token cached by the last [`DeviceFlow::login`] to github.com
+pub fn cached_token() -> Option<String> {
+    cached_host_token("github.com")
+}
+
+/// The token cached by the last [`DeviceFlow::login`] to `host`, such as
+/// `github.mycorp.com` for a GitHub Enterprise Server
+pub fn cached_host_token(host: &str) -> Option<String> {
+    #[cfg(feature = "keyring")]
+    if let Some(token) = super::keyring::load_token(host) {
+        return Some(token);
+    }
+    let token = fs::read_to_string(token_path(host)?).ok()?;
+    Some(token.trim().to_string()).filter(|token| !token.is_empty())
+}
@@ -0,0 +1,37 @@
+use std::{fs, path::PathBuf};
+
+fn hosts_path() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("GH_CONFIG_DIR") {
+        return Some(PathBuf::from(dir).join("hosts.yml"));
+    }
+    // gh names its windows config directory after the product
+    let dir = match cfg!(windows) {
+        true => "GitHub CLI",
+        false => "gh",
+    };
+    Some(super::config_dir()?.join(dir).join("hosts.yml"))
+}
+
+/// The `oauth_token` the gh cli stored for `host` in its `hosts.yml`, such as
+///
+/// ```yaml
+/// github.com:
+///     user: levinion
+///     oauth_token: gho_xxx
+/// ```
+pub(crate) fn gh_token(host: &str) -> Option<String> {
+    let hosts = fs::read_to_string(hosts_path()?).ok()?;
+    let mut in_host = false;
+    for line in hosts.lines() {
+        if !line.starts_with(' ') && !line.starts_with('\t') {
+            in_host = line.trim_end().trim_end_matches(':') == host;
+            continue;
+        }
+        if let Some(token) = line.trim().strip_prefix("oauth_token:") {
+            if in_host {
+                return Some(token.trim().trim_matches('"').to_string());
+            }
+        }
+    }
+    None
+}
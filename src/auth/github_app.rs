@@ -0,0 +1,126 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use reqwest::Client;
+use tokio::sync::Mutex;
+
+use super::TokenSource;
+use crate::{provider::base_url, Remote};
+
+#[derive(serde::Serialize)]
+struct Claims {
+    iat: u64,
+    exp: u64,
+    iss: String,
+}
+
+#[derive(serde::Deserialize)]
+struct Installation {
+    id: u64,
+}
+
+#[derive(serde::Deserialize)]
+struct AccessToken {
+    token: String,
+}
+
+/// Authenticates as a GitHub App installation, minting short-lived installation tokens
+pub struct GitHubApp {
+    app_id: String,
+    key: EncodingKey,
+    installation: Option<u64>,
+    api: String,
+    cached: Mutex<Option<(String, Instant)>>,
+}
+
+impl GitHubApp {
+    // installation tokens expire after an hour, refresh them a bit earlier
+    const TOKEN_TTL: Duration = Duration::from_secs(50 * 60);
+
+    /// `private_key` is the PEM encoded RSA key generated for the app
+    pub fn new(app_id: &str, private_key: &[u8]) -> Result<Self> {
+        Ok(Self {
+            app_id: app_id.into(),
+            key: EncodingKey::from_rsa_pem(private_key)?,
+            installation: None,
+            api: "https://api.github.com".into(),
+            cached: Mutex::new(None),
+        })
+    }
+
+    /// Installation to authenticate as, looked up from the repo when not given
+    pub fn installation(mut self, id: u64) -> Self {
+        self.installation = Some(id);
+        self
+    }
+
+    /// Api host of a GitHub Enterprise Server, such as `https://github.mycorp.com/api/v3`
+    pub fn api(mut self, api: &str) -> Self {
+        self.api = base_url(api);
+        self
+    }
+
+    fn jwt(&self) -> Result<String> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        // backdate against clock drift, github allows at most ten minutes of validity
+        let claims = Claims {
+            iat: now - 60,
+            exp: now + 9 * 60,
+            iss: self.app_id.clone(),
+        };
+        Ok(jsonwebtoken::encode(
+            &Header::new(Algorithm::RS256),
+            &claims,
+            &self.key,
+        )?)
+    }
+
+    async fn installation_id(&self, client: &Client, remote: &Remote, jwt: &str) -> Result<u64> {
+        if let Some(id) = self.installation {
+            return Ok(id);
+        }
+        let url = format!(
+            "{}/repos/{}/{}/installation",
+            self.api, remote.user, remote.repo
+        );
+        let res = client
+            .get(url)
+            .bearer_auth(jwt)
+            .header("Accept", "application/vnd.github+json")
+            .send()
+            .await?
+            .text()
+            .await?;
+        let installation: Installation =
+            serde_json::from_str(&res).map_err(|_| anyhow!("Is the app installed on the repo?"))?;
+        Ok(installation.id)
+    }
+}
+
+#[async_trait::async_trait]
+impl TokenSource for GitHubApp {
+    async fn token(&self, client: &Client, remote: &Remote) -> Result<String> {
+        let mut cached = self.cached.lock().await;
+        if let Some((token, minted)) = cached.as_ref() {
+            if minted.elapsed() < Self::TOKEN_TTL {
+                return Ok(token.clone());
+            }
+        }
+        let jwt = self.jwt()?;
+        let id = self.installation_id(client, remote, &jwt).await?;
+        let url = format!("{}/app/installations/{id}/access_tokens", self.api);
+        let res = client
+            .post(url)
+            .bearer_auth(&jwt)
+            .header("Accept", "application/vnd.github+json")
+            .send()
+            .await?
+            .text()
+            .await?;
+        let access: AccessToken = serde_json::from_str(&res)
+            .map_err(|_| anyhow!("Failed to create an installation token"))?;
+        *cached = Some((access.token.clone(), Instant::now()));
+        Ok(access.token)
+    }
+}
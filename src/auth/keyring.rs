@@ -0,0 +1,21 @@
+use anyhow::Result;
+use keyring::Entry;
+
+const SERVICE: &str = "gitload";
+
+/// Stores the token for `host`, such as github.com, in the platform secret store
+pub fn store_token(host: &str, token: &str) -> Result<()> {
+    Entry::new(SERVICE, host)?.set_password(token)?;
+    Ok(())
+}
+
+/// The token stored for `host` in the platform secret store
+pub fn load_token(host: &str) -> Option<String> {
+    Entry::new(SERVICE, host).ok()?.get_password().ok()
+}
+
+/// Removes the token stored for `host` from the platform secret store
+pub fn delete_token(host: &str) -> Result<()> {
+    Entry::new(SERVICE, host)?.delete_credential()?;
+    Ok(())
+}
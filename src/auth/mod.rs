@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use reqwest::Client;
+
+use crate::Remote;
+
+mod device;
+mod gh;
+#[cfg(feature = "github-app")]
+mod github_app;
+#[cfg(feature = "keyring")]
+pub mod keyring;
+mod netrc;
+mod pool;
+
+pub use device::{cached_host_token, cached_token, DeviceCode, DeviceFlow};
+pub(crate) use gh::gh_token;
+#[cfg(feature = "github-app")]
+pub use github_app::GitHubApp;
+use netrc::netrc_password;
+pub use pool::TokenPool;
+
+/// Something handing out the token for the next request, it may refresh the token on its own
+#[async_trait::async_trait]
+pub trait TokenSource: Send + Sync {
+    async fn token(&self, client: &Client, remote: &Remote) -> Result<String>;
+
+    /// Called when `token` was rate limited, returns whether another token is worth a retry
+    fn rejected(&self, _token: &str) -> bool {
+        false
+    }
+}
+
+#[async_trait::async_trait]
+impl TokenSource for String {
+    async fn token(&self, _client: &Client, _remote: &Remote) -> Result<String> {
+        Ok(self.clone())
+    }
+}
+
+// a token kept outside the environment, in the secret store, the device flow cache or netrc
+pub(crate) fn stored_token(host: &str) -> Option<String> {
+    cached_host_token(host).or_else(|| netrc_password(host))
+}
+
+// the per-user config directory, such as ~/.config
+fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .or_else(|| std::env::var_os("APPDATA"))
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".config")))
+}
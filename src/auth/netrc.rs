@@ -0,0 +1,110 @@
+use std::{fs, path::PathBuf};
+
+fn netrc_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("NETRC") {
+        return Some(PathBuf::from(path));
+    }
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    let name = match cfg!(windows) {
+        true => "_netrc",
+        false => ".netrc",
+    };
+    Some(PathBuf::from(home).join(name))
+}
+
+/// The password of the `machine` entry for `host` in the netrc file, or of its `default` entry
+pub(crate) fn netrc_password(host: &str) -> Option<String> {
+    password(&fs::read_to_string(netrc_path()?).ok()?, host)
+}
+
+fn password(netrc: &str, host: &str) -> Option<String> {
+    let mut lines = netrc.lines();
+    let mut tokens = vec![];
+    // macdef bodies run until an empty line and must not be parsed as tokens
+    while let Some(line) = lines.next() {
+        let words: Vec<_> = line.split_whitespace().collect();
+        if words.first() == Some(&"macdef") {
+            lines
+                .by_ref()
+                .take_while(|line| !line.trim().is_empty())
+                .for_each(drop);
+            continue;
+        }
+        tokens.extend(words);
+    }
+
+    let mut matched = false;
+    let mut default = None;
+    let mut is_default = false;
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" => {
+                matched = tokens.next() == Some(host);
+                is_default = false;
+            }
+            "default" => {
+                matched = false;
+                is_default = true;
+            }
+            "password" => {
+                let password = tokens.next()?;
+                if matched {
+                    return Some(password.to_string());
+                }
+                if is_default {
+                    default = Some(password.to_string());
+                }
+            }
+            "login" | "account" => {
+                tokens.next();
+            }
+            _ => {}
+        }
+    }
+    default
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_password_of_the_machine() {
+        let netrc = "machine gitlab.com login me password one\n\
+                     machine github.com\n  login me\n  password two\n";
+        assert_eq!(password(netrc, "github.com").as_deref(), Some("two"));
+        assert_eq!(password(netrc, "gitlab.com").as_deref(), Some("one"));
+    }
+
+    #[test]
+    fn falls_back_to_the_default_entry() {
+        let netrc = "machine gitlab.com password one\ndefault login me password fallback\n";
+        assert_eq!(password(netrc, "github.com").as_deref(), Some("fallback"));
+        assert_eq!(password(netrc, "gitlab.com").as_deref(), Some("one"));
+    }
+
+    #[test]
+    fn skips_macro_definitions() {
+        let netrc = "macdef init\nmachine github.com password macro\n\n\
+                     machine github.com password real\n";
+        assert_eq!(password(netrc, "github.com").as_deref(), Some("real"));
+    }
+
+    #[test]
+    fn answers_none_without_a_matching_entry() {
+        assert_eq!(
+            password("machine gitlab.com password one", "github.com"),
+            None
+        );
+        assert_eq!(password("", "github.com"), None);
+        // a password token with nothing after it
+        assert_eq!(password("machine github.com password", "github.com"), None);
+    }
+
+    #[test]
+    fn reads_login_and_account_values_as_values() {
+        let netrc = "machine github.com login password account machine password real";
+        assert_eq!(password(netrc, "github.com").as_deref(), Some("real"));
+    }
+}
@@ -0,0 +1,63 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use reqwest::Client;
+
+use super::TokenSource;
+use crate::Remote;
+
+/// Rotates between several tokens per request, resting the ones that hit their rate limit
+pub struct TokenPool {
+    tokens: Vec<String>,
+    next: AtomicUsize,
+    exhausted: Mutex<Vec<Option<Instant>>>,
+}
+
+impl TokenPool {
+    // the primary rate limit of github resets hourly
+    const REST: Duration = Duration::from_secs(60 * 60);
+
+    pub fn new(tokens: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        let tokens: Vec<String> = tokens.into_iter().map(Into::into).collect();
+        Self {
+            next: AtomicUsize::new(0),
+            exhausted: Mutex::new(vec![None; tokens.len()]),
+            tokens,
+        }
+    }
+
+    fn is_resting(since: &Option<Instant>) -> bool {
+        since.is_some_and(|since| since.elapsed() < Self::REST)
+    }
+}
+
+#[async_trait::async_trait]
+impl TokenSource for TokenPool {
+    async fn token(&self, _client: &Client, _remote: &Remote) -> Result<String> {
+        if self.tokens.is_empty() {
+            return Err(anyhow!("The token pool is empty"));
+        }
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let exhausted = self.exhausted.lock().unwrap();
+        // when every token rests, hand out the next one anyway and let the request fail
+        let index = (start..start + self.tokens.len())
+            .map(|i| i % self.tokens.len())
+            .find(|&i| !Self::is_resting(&exhausted[i]))
+            .unwrap_or(start % self.tokens.len());
+        Ok(self.tokens[index].clone())
+    }
+
+    fn rejected(&self, token: &str) -> bool {
+        let mut exhausted = self.exhausted.lock().unwrap();
+        if let Some(i) = self.tokens.iter().position(|t| t == token) {
+            exhausted[i] = Some(Instant::now());
+        }
+        exhausted.iter().any(|since| !Self::is_resting(since))
+    }
+}
@@ -0,0 +1,153 @@
+use std::{path::PathBuf, sync::Arc};
+
+use futures_util::{future::join_all, stream::unfold, Stream};
+use tokio::sync::Semaphore;
+
+use crate::{
+    AbortOnDrop, DownloadEvent, DownloadReport, Downloader, DownloaderBuilder, Error,
+    ProgressAggregator,
+};
+
+type BatchEventHandler = Arc<dyn Fn(usize, &DownloadEvent) + Send + Sync>;
+
+/// Several downloads, such as of a path of many repos, run at once with one http client,
+/// one budget of files downloaded at the same time and one stream of events, the building
+/// block of a mirroring tool
+#[derive(Clone)]
+pub struct BatchDownloader {
+    jobs: Vec<Downloader>,
+}
+
+impl BatchDownloader {
+    pub fn builder() -> BatchBuilder {
+        BatchBuilder::default()
+    }
+
+    /// Runs every job at once and answers their reports in the order they were given, a
+    /// failed job leaves the others running
+    pub async fn download(&self) -> Vec<Result<DownloadReport, Error>> {
+        join_all(self.jobs.iter().map(|job| job.download())).await
+    }
+
+    /// Downloads in the background and yields the events of every job with its index,
+    /// ending with [`DownloadEvent::Failed`] for a failed job, the downloads are aborted
+    /// when the stream is dropped
+    pub fn stream(&self) -> impl Stream<Item = (usize, DownloadEvent)> + Send {
+        let mut batch = self.clone();
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        for (index, job) in batch.jobs.iter_mut().enumerate() {
+            let handler = job.event_handler.clone();
+            let events = tx.clone();
+            job.event_handler = Arc::new(move |event| {
+                handler(event);
+                events.send((index, event.clone())).ok();
+            });
+        }
+        let download = tokio::spawn(async move {
+            for (index, res) in batch.download().await.into_iter().enumerate() {
+                if let Err(err) = res {
+                    let error = Arc::new(err);
+                    tx.send((index, DownloadEvent::Failed { error })).ok();
+                }
+            }
+        });
+        let state = (rx, AbortOnDrop(download));
+        unfold(state, |(mut rx, download)| async move {
+            let event = rx.recv().await?;
+            Some((event, (rx, download)))
+        })
+    }
+}
+
+/// Gathers the jobs of a [`BatchDownloader`], each one a [`DownloaderBuilder`] of its own
+#[derive(Default)]
+pub struct BatchBuilder {
+    jobs: Vec<DownloaderBuilder>,
+    client: Option<reqwest::Client>,
+    max_concurrency: Option<usize>,
+    event_handler: Option<BatchEventHandler>,
+    progress: Option<ProgressAggregator>,
+}
+
+impl BatchBuilder {
+    /// Download `path` of `repo`, such as levinion/dotfiles, at `reference` into exactly
+    /// `destination`
+    pub fn job(
+        self,
+        repo: &str,
+        reference: impl Into<String>,
+        path: impl Into<String>,
+        destination: impl Into<PathBuf>,
+    ) -> Self {
+        // an unowned repo is refused as an empty user when the batch is built
+        let (user, repo) = repo.split_once('/').unwrap_or(("", repo));
+        let mut job = DownloaderBuilder::new(user, repo, path).branch(reference);
+        job.local_path = Some(destination.into());
+        self.downloader(job)
+    }
+
+    /// A job with settings of its own, such as another provider or filters, its
+    /// concurrency is left to the batch
+    pub fn downloader(mut self, job: DownloaderBuilder) -> Self {
+        self.jobs.push(job);
+        self
+    }
+
+    /// Share an http client with the jobs that have none of their own
+    pub fn client(mut self, client: reqwest::Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Files downloaded at the same time across all the jobs, 16 by default
+    pub fn max_concurrency(mut self, n: usize) -> Self {
+        self.max_concurrency = Some(n.max(1));
+        self
+    }
+
+    /// Called with the index of the job and every [`DownloadEvent`] of it, after the
+    /// handler the job has of its own
+    pub fn on_event(mut self, f: impl Fn(usize, &DownloadEvent) + Send + Sync + 'static) -> Self {
+        self.event_handler = Some(Arc::new(f));
+        self
+    }
+
+    /// Report every job into `aggregator`, named such as levinion/dotfiles/nvim
+    pub fn progress(mut self, aggregator: &ProgressAggregator) -> Self {
+        self.progress = Some(aggregator.clone());
+        self
+    }
+
+    /// Builds every job, answering the error of the first one whose settings are refused
+    pub fn build(self) -> Result<BatchDownloader, Error> {
+        let client = match self.client {
+            Some(client) => client,
+            None => Downloader::default_client().build()?,
+        };
+        let max_concurrency = self.max_concurrency.unwrap_or(Downloader::MAX_CONCURRENCY);
+        let permits = Arc::new(Semaphore::new(max_concurrency));
+        let mut jobs = vec![];
+        for (index, mut job) in self.jobs.into_iter().enumerate() {
+            job.client.get_or_insert_with(|| client.clone());
+            let mut job = job.build()?;
+            job.permits = Some(permits.clone());
+            if let Some(aggregator) = &self.progress {
+                let name = [job.remote.user(), job.remote.repo(), &job.remote_path]
+                    .into_iter()
+                    .filter(|part| !part.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                job.observers.push(Arc::new(aggregator.job(name)));
+            }
+            if let Some(batch) = self.event_handler.clone() {
+                let handler = job.event_handler.clone();
+                job.event_handler = Arc::new(move |event| {
+                    handler(event);
+                    batch(index, event);
+                });
+            }
+            jobs.push(job);
+        }
+        Ok(BatchDownloader { jobs })
+    }
+}
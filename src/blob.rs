@@ -0,0 +1,39 @@
+use std::{fs::File, io, path::Path};
+
+use anyhow::Result;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+// the sha git gives the file's content, a `blob <len>\0` header followed by the bytes
+pub(crate) fn blob_sha(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha1::new();
+    hasher.update(format!("blob {}\0", file.metadata()?.len()));
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+// the sha git gives content held in memory, such as the target of a symlink
+pub(crate) fn content_sha(content: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("blob {}\0", content.len()));
+    hasher.update(content);
+    hex(&hasher.finalize())
+}
+
+// the sha256 git lfs names an object by
+pub(crate) fn lfs_oid(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+// names a file by a sha1 of its path, such as while its blob sha is still unknown
+pub(crate) fn path_sha(path: &str) -> String {
+    hex(&Sha1::digest(path))
+}
+
+fn hex(sha: &[u8]) -> String {
+    sha.iter().map(|byte| format!("{byte:02x}")).collect()
+}
@@ -0,0 +1,316 @@
+use std::{
+    fs::{self, File},
+    future::Future,
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{ready, Context, Poll},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use flate2::{write::GzEncoder, Compression};
+use tar::{EntryType, Header};
+use tokio::{io::AsyncWrite, task::JoinHandle};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+use crate::{Entry, EntryKind};
+
+/// The single archives the files can be written into instead of a directory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Format {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+enum Archive {
+    Zip(Box<ZipWriter<BufWriter<File>>>),
+    Tar(tar::Builder<BufWriter<File>>),
+    TarGz(Box<tar::Builder<GzEncoder<BufWriter<File>>>>),
+}
+
+// an archive being written, the files are added one after another as each arrives,
+// one left unfinished by a failed download is removed
+pub(crate) struct Bundle {
+    path: PathBuf,
+    archive: Mutex<Option<Archive>>,
+    spilled: AtomicUsize,
+}
+
+impl Bundle {
+    pub(crate) fn create(format: Format, path: &Path) -> io::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = BufWriter::new(File::create(path)?);
+        let archive = match format {
+            Format::Zip => Archive::Zip(Box::new(ZipWriter::new(file))),
+            Format::Tar => Archive::Tar(tar::Builder::new(file)),
+            Format::TarGz => {
+                let file = GzEncoder::new(file, Compression::default());
+                Archive::TarGz(Box::new(tar::Builder::new(file)))
+            }
+        };
+        Ok(Self {
+            path: path.into(),
+            archive: Mutex::new(Some(archive)),
+            spilled: AtomicUsize::new(0),
+        })
+    }
+
+    // a file of its own next to the archive, for a file too large to be held in memory
+    fn spill_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(
+            ".{}.part",
+            self.spilled.fetch_add(1, Ordering::Relaxed)
+        ));
+        path.into()
+    }
+
+    fn add(&self, name: &str, entry: &Entry, mut content: impl Read, size: u64) -> Result<()> {
+        let mut archive = self.archive.lock().unwrap();
+        let archive = archive
+            .as_mut()
+            .ok_or_else(|| anyhow!("{} is already finished", self.path.display()))?;
+        match archive {
+            Archive::Zip(zip) => {
+                let options = SimpleFileOptions::default().large_file(size >= u32::MAX as u64);
+                if entry.kind == EntryKind::Symlink {
+                    zip.add_symlink(name, target(content)?, options)?;
+                    return Ok(());
+                }
+                let options = match entry.mode {
+                    Some(mode) => options.unix_permissions(mode & 0o777),
+                    None => options,
+                };
+                zip.start_file(name, options)?;
+                io::copy(&mut content, zip)?;
+            }
+            Archive::Tar(tar) => append(tar, name, entry, content, size)?,
+            Archive::TarGz(tar) => append(&mut **tar, name, entry, content, size)?,
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(&self) -> Result<()> {
+        match self.archive.lock().unwrap().take() {
+            Some(Archive::Zip(zip)) => zip.finish()?.flush()?,
+            Some(Archive::Tar(tar)) => tar.into_inner()?.flush()?,
+            Some(Archive::TarGz(tar)) => tar.into_inner()?.finish()?.flush()?,
+            None => {}
+        }
+        Ok(())
+    }
+
+    // the writer a file is fetched into, it is added as `name` once shut down
+    pub(crate) fn sink(
+        self: &Arc<Self>,
+        name: &str,
+        entry: &Entry,
+    ) -> Pin<Box<dyn AsyncWrite + Send>> {
+        Box::pin(EntrySink {
+            bundle: self.clone(),
+            name: name.into(),
+            entry: entry.clone(),
+            content: Content::Memory(vec![]),
+            adding: None,
+        })
+    }
+}
+
+fn target(mut content: impl Read) -> io::Result<String> {
+    let mut target = vec![];
+    content.read_to_end(&mut target)?;
+    Ok(String::from_utf8_lossy(&target).into_owned())
+}
+
+fn append(
+    tar: &mut tar::Builder<impl Write>,
+    name: &str,
+    entry: &Entry,
+    content: impl Read,
+    size: u64,
+) -> io::Result<()> {
+    let mut header = Header::new_gnu();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    header.set_mtime(now.as_secs());
+    if entry.kind == EntryKind::Symlink {
+        header.set_entry_type(EntryType::Symlink);
+        header.set_mode(0o777);
+        header.set_size(0);
+        return tar.append_link(&mut header, name, target(content)?);
+    }
+    header.set_mode(entry.mode.map_or(0o644, |mode| mode & 0o777));
+    header.set_size(size);
+    tar.append_data(&mut header, name, content)
+}
+
+impl Drop for Bundle {
+    fn drop(&mut self) {
+        if let Ok(Some(_)) = self.archive.get_mut().map(Option::take) {
+            fs::remove_file(&self.path).ok();
+        }
+    }
+}
+
+// the content of a file fetched so far, moved to a file of its own once past SPILL_SIZE
+enum Content {
+    Memory(Vec<u8>),
+    Spilled(PathBuf, tokio::fs::File),
+}
+
+// a file larger than this doesn't stay in memory until it is added
+const SPILL_SIZE: usize = 1024 * 1024;
+
+// buffers a file, so the files fetched at the same time don't interleave in the archive,
+// an attempt given up on is dropped without being added
+struct EntrySink {
+    bundle: Arc<Bundle>,
+    name: String,
+    entry: Entry,
+    content: Content,
+    adding: Option<JoinHandle<Result<()>>>,
+}
+
+impl EntrySink {
+    fn spill(&mut self) -> io::Result<()> {
+        if let Content::Memory(content) = &self.content {
+            let path = self.bundle.spill_path();
+            let mut file = File::create(&path)?;
+            file.write_all(content)?;
+            self.content = Content::Spilled(path, tokio::fs::File::from_std(file));
+        }
+        Ok(())
+    }
+}
+
+impl AsyncWrite for EntrySink {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if let Content::Memory(content) = &mut this.content {
+            if content.len() + buf.len() <= SPILL_SIZE {
+                content.extend_from_slice(buf);
+                return Poll::Ready(Ok(buf.len()));
+            }
+            this.spill()?;
+        }
+        match &mut this.content {
+            Content::Spilled(_, file) => Pin::new(file).poll_write(cx, buf),
+            Content::Memory(_) => unreachable!("the content was just spilled"),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().content {
+            Content::Spilled(_, file) => Pin::new(file).poll_flush(cx),
+            Content::Memory(_) => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        let this = self.get_mut();
+        let adding = this.adding.get_or_insert_with(|| {
+            let bundle = this.bundle.clone();
+            let name = std::mem::take(&mut this.name);
+            let entry = this.entry.clone();
+            let (content, spilled) = match &mut this.content {
+                Content::Memory(content) => (std::mem::take(content), None),
+                Content::Spilled(path, _) => (vec![], Some(path.clone())),
+            };
+            tokio::task::spawn_blocking(move || match spilled {
+                None => bundle.add(&name, &entry, &content[..], content.len() as u64),
+                Some(path) => {
+                    let file = File::open(path)?;
+                    let size = file.metadata()?.len();
+                    bundle.add(&name, &entry, io::BufReader::new(file), size)
+                }
+            })
+        });
+        let added = ready!(Pin::new(adding).poll(cx));
+        Poll::Ready(
+            added
+                .map_err(io::Error::other)
+                .and_then(|added| added.map_err(io::Error::other)),
+        )
+    }
+}
+
+impl Drop for EntrySink {
+    fn drop(&mut self) {
+        if let Content::Spilled(path, _) = &self.content {
+            fs::remove_file(path).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+    use crate::stub::TempDir;
+
+    fn file(path: &str) -> Entry {
+        Entry {
+            path: path.into(),
+            kind: EntryKind::File,
+            size: None,
+            sha: None,
+            mode: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn spills_a_large_file_until_it_is_added() {
+        let dir = TempDir::new();
+        let path = dir.path().join("out.tar");
+        let bundle = Arc::new(Bundle::create(Format::Tar, &path).unwrap());
+        let large = vec![b'a'; 3 * SPILL_SIZE];
+        let mut sink = bundle.sink("large.bin", &file("large.bin"));
+        sink.write_all(&large[..2 * SPILL_SIZE]).await.unwrap();
+        let spills = || {
+            fs::read_dir(dir.path())
+                .unwrap()
+                .filter(|file| file.as_ref().unwrap().path().extension() == Some("part".as_ref()))
+                .count()
+        };
+        assert_eq!(spills(), 1);
+
+        let mut small = bundle.sink("small.txt", &file("small.txt"));
+        small.write_all(b"small").await.unwrap();
+        small.shutdown().await.unwrap();
+        sink.write_all(&large[2 * SPILL_SIZE..]).await.unwrap();
+        sink.shutdown().await.unwrap();
+        drop(sink);
+        assert_eq!(spills(), 0);
+        bundle.finish().unwrap();
+
+        let mut archive = tar::Archive::new(File::open(&path).unwrap());
+        let files: Vec<_> = archive
+            .entries()
+            .unwrap()
+            .map(|file| {
+                let mut file = file.unwrap();
+                let mut content = vec![];
+                file.read_to_end(&mut content).unwrap();
+                (file.path().unwrap().display().to_string(), content)
+            })
+            .collect();
+        assert_eq!(
+            files,
+            [
+                ("small.txt".to_string(), b"small".to_vec()),
+                ("large.bin".to_string(), large),
+            ]
+        );
+    }
+}
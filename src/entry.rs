@@ -0,0 +1,79 @@
+use crate::Node;
+
+/// What an [`Entry`] is, told apart by its git mode where the provider reports it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    /// A commit of another repo, which isn't downloaded
+    Submodule,
+}
+
+/// An entry of the remote tree, as answered by [`Downloader::list`](crate::Downloader::list)
+/// and handed to the events, the observers and the report
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// Relative to the repo root, such as nvim/init.lua
+    pub path: String,
+    pub kind: EntryKind,
+    /// In bytes, when the provider reports it
+    pub size: Option<u64>,
+    /// The git object sha, when the provider reports it
+    pub sha: Option<String>,
+    /// The git mode, such as 0o100755 for an executable, when the provider reports it
+    pub mode: Option<u32>,
+}
+
+impl Entry {
+    const SYMLINK: u32 = 0o120000;
+
+    // entries of unknown kinds are left out
+    pub(crate) fn from_node(node: Node) -> Option<Self> {
+        let mode = node
+            .mode
+            .as_deref()
+            .and_then(|mode| u32::from_str_radix(mode, 8).ok());
+        let kind = match (node.kind.as_str(), mode) {
+            ("blob", Some(Self::SYMLINK)) => EntryKind::Symlink,
+            ("blob", _) => EntryKind::File,
+            ("tree", _) => EntryKind::Directory,
+            ("commit", _) => EntryKind::Submodule,
+            _ => return None,
+        };
+        Some(Self {
+            path: node.path,
+            kind,
+            size: node.size,
+            sha: node.sha,
+            mode,
+        })
+    }
+
+    // a file unpacked from an archive, whose header only carries the permissions
+    pub(crate) fn unpacked(path: String, size: u64, symlink: bool, permissions: u32) -> Self {
+        let (kind, mode) = match (symlink, permissions & 0o111 != 0) {
+            (true, _) => (EntryKind::Symlink, Self::SYMLINK),
+            (false, true) => (EntryKind::File, 0o100755),
+            (false, false) => (EntryKind::File, 0o100644),
+        };
+        Self {
+            path,
+            kind,
+            size: Some(size),
+            sha: None,
+            mode: Some(mode),
+        }
+    }
+
+    /// Whether git marks the file executable, unknown without the mode
+    pub fn is_executable(&self) -> bool {
+        self.mode
+            .is_some_and(|mode| mode & 0o111 != 0 && mode >> 12 == 0o10)
+    }
+
+    // the files and symlinks, which are written
+    pub(crate) fn is_blob(&self) -> bool {
+        matches!(self.kind, EntryKind::File | EntryKind::Symlink)
+    }
+}
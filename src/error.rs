@@ -0,0 +1,101 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use reqwest::Response;
+
+use crate::{RateLimit, Remote};
+
+/// The ways a download fails, as answered by [`Downloader::download`](crate::Downloader::download),
+/// the errors of single files in [`DownloadEvent`](crate::DownloadEvent)s downcast to it
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("Are you sure the repo {user}/{repo} really exists?")]
+    RepoNotFound { user: String, repo: String },
+    #[error("Are you sure the ref {0} really exists?")]
+    BranchNotFound(String),
+    #[error("Are you sure the target name {0} is right?")]
+    PathNotFound(String),
+    #[error("The rate limit is used up until {reset:?}")]
+    RateLimited {
+        reset: SystemTime,
+        /// The quota last reported, none when the server only asked to back off
+        quota: Option<RateLimit>,
+    },
+    #[error(transparent)]
+    Network(#[from] reqwest::Error),
+    #[error("Couldn't write {}", path.display())]
+    WriteFailed {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Invalid {field}: {reason}")]
+    InvalidSetting { field: &'static str, reason: String },
+    #[error("The download holds {actual} {what}, more than the limit of {limit}")]
+    LimitExceeded {
+        what: &'static str,
+        actual: u64,
+        limit: u64,
+    },
+    #[error("{first} and {second} would both be written to {}", path.display())]
+    Collision {
+        path: PathBuf,
+        first: String,
+        second: String,
+    },
+    #[error("{} already exists", .0.display())]
+    FileExists(PathBuf),
+    #[error("The download was cancelled")]
+    Cancelled,
+    #[error("The download didn't finish within {0:?}")]
+    DeadlineExceeded(Duration),
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl Error {
+    pub(crate) fn repo_not_found(remote: &Remote) -> Self {
+        Self::RepoNotFound {
+            user: remote.user.clone(),
+            repo: remote.repo.clone(),
+        }
+    }
+
+    // a response refused because the quota is used up
+    pub(crate) fn rate_limited(res: &Response) -> Option<Self> {
+        let limited = matches!(res.status().as_u16(), 403 | 429);
+        let limit = RateLimit::from_headers(res.headers()).filter(|limit| limit.is_exhausted())?;
+        limited.then_some(Self::RateLimited {
+            reset: limit.reset,
+            quota: Some(limit),
+        })
+    }
+
+    // a request still rate limited after the attempts it was given, `wait` being how long
+    // the server asked to back off
+    pub(crate) fn still_limited(quota: Option<RateLimit>, wait: Option<Duration>) -> Self {
+        let reset = match (quota, wait) {
+            (_, Some(wait)) => SystemTime::now() + wait,
+            (Some(quota), None) => quota.reset,
+            (None, None) => SystemTime::now(),
+        };
+        Self::RateLimited { reset, quota }
+    }
+}
+
+// the internals pass anyhow errors around, the typed ones among them are recovered
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<Self>() {
+            Ok(err) => return err,
+            Err(err) => err,
+        };
+        match err.downcast::<reqwest::Error>() {
+            Ok(err) => Self::Network(err),
+            Err(err) => Self::Other(err),
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Result;
+
+/// ETags of the files downloaded before, kept next to the destination so a later run
+/// sends conditional requests and only transfers what changed
+pub(crate) struct ETags {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, String>>,
+}
+
+impl ETags {
+    const FILE_NAME: &'static str = ".gitload-etags.json";
+
+    // such as .gitload-etags.json beside src when downloading into src
+    pub(crate) fn path(local_path: &Path) -> PathBuf {
+        let dir = local_path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        dir.join(Self::FILE_NAME)
+    }
+
+    pub(crate) fn load(local_path: &Path) -> Self {
+        let path = Self::path(local_path);
+        let entries = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    // keyed by the local file, taken out while it is rewritten so an interrupted write
+    // isn't mistaken for an unchanged file later
+    pub(crate) fn take(&self, dst: &Path) -> Option<String> {
+        let key = dst.to_string_lossy();
+        let etag = self.entries.lock().unwrap().remove(key.as_ref())?;
+        dst.is_file().then_some(etag)
+    }
+
+    pub(crate) fn set(&self, dst: &Path, etag: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(dst.to_string_lossy().into_owned(), etag.to_string());
+    }
+
+    pub(crate) async fn save(&self) -> Result<()> {
+        let content = serde_json::to_string_pretty(&*self.entries.lock().unwrap())?;
+        tokio::fs::write(&self.path, content).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_etags_beside_the_local_path() {
+        let path = |local: &str| ETags::path(Path::new(local));
+        assert_eq!(
+            path("dotfiles/nvim"),
+            Path::new("dotfiles/.gitload-etags.json")
+        );
+        assert_eq!(path("nvim"), Path::new("./.gitload-etags.json"));
+        // within the local path itself, where a sync must leave it
+        assert_eq!(path("."), Path::new("./.gitload-etags.json"));
+    }
+}
@@ -0,0 +1,39 @@
+use std::{path::PathBuf, sync::Arc};
+
+use crate::{DownloadReport, Entry, Error, Process};
+
+/// What happens during a download, as handed to
+/// [`DownloaderBuilder::on_event`](crate::DownloaderBuilder::on_event)
+#[derive(Clone, Debug)]
+pub enum DownloadEvent {
+    /// The tree is listed and `files` are going to be downloaded
+    Started { files: usize },
+    /// A file waits for its turn
+    FileQueued { entry: Entry },
+    /// A file is written with `bytes`, in a dry run nothing is written and `would_download`
+    /// tells whether it would be fetched or left as is
+    FileCompleted {
+        entry: Entry,
+        bytes: u64,
+        process: Process,
+        would_download: bool,
+    },
+    /// A local file is left as is, as it matches the remote one or the
+    /// [`OverwritePolicy`](crate::OverwritePolicy) skips it
+    FileUpToDate { entry: Entry, process: Process },
+    /// A sync removed a local file missing from the remote tree, in a dry run it would be
+    FileDeleted { path: PathBuf },
+    /// A file failed, the error may be downcast such as to an [`Error`] or a
+    /// [`reqwest::Error`]
+    FileFailed {
+        entry: Entry,
+        error: Arc<anyhow::Error>,
+    },
+    /// Every file is written
+    Finished { report: DownloadReport },
+    /// The download stopped, after [`DownloadEvent::FileFailed`] when a file caused it,
+    /// only sent by [`Downloader::stream`](crate::Downloader::stream),
+    /// [`Downloader::watch`](crate::Downloader::watch) and
+    /// [`BatchDownloader::stream`](crate::BatchDownloader::stream)
+    Failed { error: Arc<Error> },
+}
@@ -0,0 +1,91 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::GitignoreBuilder;
+
+use crate::{Entry, EntryFilter};
+
+// the path patterns are matched against, relative to the remote path, or the file name when
+// the remote path is the file itself
+pub(crate) fn relative<'a>(path: &'a str, remote_path: &str) -> &'a str {
+    match path.strip_prefix(remote_path) {
+        Some("") | None => Path::new(path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(path),
+        Some(rest) => rest.trim_start_matches('/'),
+    }
+}
+
+fn glob_set(patterns: &[String]) -> Result<GlobSet, globset::Error> {
+    let mut globs = GlobSetBuilder::new();
+    for pattern in patterns {
+        globs.add(Glob::new(pattern)?);
+    }
+    globs.build()
+}
+
+// files matching one of the included globs, when there are any, and none of the excluded
+pub(crate) fn globs(
+    include: &[String],
+    exclude: &[String],
+    remote_path: &str,
+) -> Result<Option<EntryFilter>, globset::Error> {
+    if include.is_empty() && exclude.is_empty() {
+        return Ok(None);
+    }
+    let (included, excluded) = (glob_set(include)?, glob_set(exclude)?);
+    let remote_path = remote_path.to_string();
+    let all = include.is_empty();
+    Ok(Some(Arc::new(move |entry: &Entry| {
+        let path = relative(&entry.path, &remote_path);
+        (all || included.is_match(path)) && !excluded.is_match(path)
+    })))
+}
+
+// files the rules of the gitignore-style files don't exclude, later rules win as in git
+pub(crate) fn ignore_files(
+    files: &[PathBuf],
+    remote_path: &str,
+) -> Result<Option<EntryFilter>, String> {
+    if files.is_empty() {
+        return Ok(None);
+    }
+    let mut rules = GitignoreBuilder::new(".");
+    for file in files {
+        let content =
+            std::fs::read_to_string(file).map_err(|err| format!("{}: {err}", file.display()))?;
+        for line in content.lines() {
+            rules
+                .add_line(Some(file.clone()), line)
+                .map_err(|err| err.to_string())?;
+        }
+    }
+    let rules = rules.build().map_err(|err| err.to_string())?;
+    let remote_path = remote_path.to_string();
+    Ok(Some(Arc::new(move |entry: &Entry| {
+        let path = relative(&entry.path, &remote_path);
+        !rules.matched_path_or_any_parents(path, false).is_ignore()
+    })))
+}
+
+// the same as the globs with regexes
+#[cfg(feature = "regex")]
+pub(crate) fn regexes(
+    include: Vec<regex::Regex>,
+    exclude: Vec<regex::Regex>,
+    remote_path: &str,
+) -> Option<EntryFilter> {
+    if include.is_empty() && exclude.is_empty() {
+        return None;
+    }
+    let remote_path = remote_path.to_string();
+    Some(Arc::new(move |entry: &Entry| {
+        let path = relative(&entry.path, &remote_path);
+        let included = include.is_empty() || include.iter().any(|regex| regex.is_match(path));
+        included && !exclude.iter().any(|regex| regex.is_match(path))
+    }))
+}
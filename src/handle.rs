@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+pub use tokio_util::sync::CancellationToken;
+
+/// Controls a running download from elsewhere, such as a gui or a server, got from
+/// [`Downloader::handle`](crate::Downloader::handle) before downloading
+#[derive(Clone, Debug)]
+pub struct DownloadHandle {
+    pub(crate) token: CancellationToken,
+    paused: Arc<watch::Sender<bool>>,
+}
+
+impl Default for DownloadHandle {
+    fn default() -> Self {
+        Self::new(CancellationToken::new())
+    }
+}
+
+impl DownloadHandle {
+    pub(crate) fn new(token: CancellationToken) -> Self {
+        Self {
+            token,
+            paused: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    /// Aborts the download, the files being written are removed unless resuming is on,
+    /// then they are left to be continued
+    pub fn cancel(&self) {
+        self.token.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Holds the download, no new file is fetched and the ones in flight stop reading
+    /// until [`resume`](Self::resume) is called, slow servers may drop idle connections meanwhile
+    pub fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    pub fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    // returns at once unless paused, the sender lives as long as the handle
+    pub(crate) async fn unpaused(&self) {
+        let mut paused = self.paused.subscribe();
+        paused.wait_for(|paused| !paused).await.ok();
+    }
+}
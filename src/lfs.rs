@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, ACCEPT, CONTENT_TYPE},
+    RequestBuilder,
+};
+use serde::{Deserialize, Serialize};
+
+const MEDIA_TYPE: &str = "application/vnd.git-lfs+json";
+
+// what git lfs commits in place of a file, such as
+// version https://git-lfs.github.com/spec/v1
+// oid sha256:4d7a2146...
+// size 12345
+#[derive(Serialize)]
+pub(crate) struct Pointer {
+    pub(crate) oid: String,
+    pub(crate) size: u64,
+}
+
+impl Pointer {
+    // pointer files are never larger
+    pub(crate) const MAX_SIZE: u64 = 1024;
+
+    pub(crate) fn parse(content: &[u8]) -> Option<Self> {
+        let content = std::str::from_utf8(content).ok()?;
+        let mut lines = content.lines();
+        if !lines
+            .next()?
+            .starts_with("version https://git-lfs.github.com/spec/")
+        {
+            return None;
+        }
+        let (mut oid, mut size) = (None, None);
+        for line in lines {
+            match line.split_once(' ') {
+                Some(("oid", value)) => oid = value.strip_prefix("sha256:").map(String::from),
+                Some(("size", value)) => size = value.parse().ok(),
+                _ => {}
+            }
+        }
+        Some(Self {
+            oid: oid?,
+            size: size?,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct Batch<'a> {
+    operation: &'static str,
+    transfers: [&'static str; 1],
+    objects: [&'a Pointer; 1],
+}
+
+#[derive(Deserialize)]
+struct Answer {
+    objects: Vec<Object>,
+}
+
+#[derive(Deserialize)]
+struct Object {
+    actions: Option<Actions>,
+    error: Option<ObjectError>,
+}
+
+#[derive(Deserialize)]
+struct Actions {
+    download: Action,
+}
+
+#[derive(Deserialize)]
+struct Action {
+    href: String,
+    #[serde(default)]
+    header: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct ObjectError {
+    code: u16,
+    message: String,
+}
+
+// asks the batch api where the object is downloaded from, answering the url and the
+// headers to send along
+pub(crate) async fn locate(req: RequestBuilder, pointer: &Pointer) -> Result<(String, HeaderMap)> {
+    let batch = Batch {
+        operation: "download",
+        transfers: ["basic"],
+        objects: [pointer],
+    };
+    let res = req
+        .header(ACCEPT, MEDIA_TYPE)
+        .header(CONTENT_TYPE, MEDIA_TYPE)
+        .body(serde_json::to_vec(&batch)?)
+        .send()
+        .await?
+        .error_for_status()?;
+    let answer: Answer = serde_json::from_slice(&res.bytes().await?)?;
+    let object = answer
+        .objects
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("The lfs server answered no object {}", pointer.oid))?;
+    if let Some(err) = object.error {
+        return Err(anyhow!(
+            "The lfs object {} failed with {}: {}",
+            pointer.oid,
+            err.code,
+            err.message
+        ));
+    }
+    let download = object
+        .actions
+        .ok_or_else(|| anyhow!("The lfs server has no download of {}", pointer.oid))?
+        .download;
+    let mut headers = HeaderMap::new();
+    for (name, value) in download.header {
+        headers.insert(HeaderName::try_from(name)?, HeaderValue::try_from(value)?);
+    }
+    Ok((download.href, headers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OID: &str = "4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393";
+
+    #[test]
+    fn parses_a_pointer() {
+        let content =
+            format!("version https://git-lfs.github.com/spec/v1\noid sha256:{OID}\nsize 12345\n");
+        let pointer = Pointer::parse(content.as_bytes()).unwrap();
+        assert_eq!(pointer.oid, OID);
+        assert_eq!(pointer.size, 12345);
+    }
+
+    #[test]
+    fn refuses_other_content() {
+        let missing_size =
+            format!("version https://git-lfs.github.com/spec/v1\noid sha256:{OID}\n");
+        let other_hash = "version https://git-lfs.github.com/spec/v1\noid md5:abc\nsize 1\n";
+        let no_version = format!("oid sha256:{OID}\nsize 1\n");
+        let bad_size =
+            format!("version https://git-lfs.github.com/spec/v1\noid sha256:{OID}\nsize big\n");
+        for content in [
+            "",
+            "hello world",
+            &missing_size,
+            other_hash,
+            &no_version,
+            &bad_size,
+        ] {
+            assert!(Pointer::parse(content.as_bytes()).is_none(), "{content}");
+        }
+        assert!(Pointer::parse(&[0xff, 0xfe]).is_none());
+    }
+}
@@ -1,40 +1,189 @@
 use std::{
-    fs::{create_dir_all, File},
-    io::Write,
-    path::PathBuf,
+    collections::{BTreeMap, HashMap, HashSet},
+    io::SeekFrom,
+    marker::PhantomData,
+    path::{Component, Path, PathBuf},
+    pin::Pin,
     sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Result};
-use tokio::sync::broadcast::{channel, Sender};
+use auth::TokenSource;
+use blob::{blob_sha, content_sha, lfs_oid, path_sha};
+use bundle::Bundle;
+use etag::ETags;
+use futures_util::{future::try_join_all, stream::unfold, Stream};
+use percent_encoding::percent_decode_str;
+use provider::{AzureDevOps, GitHub, GitLab, Gitea, Mirror, SourceHut};
+use rate_limit::retry_after;
+use reqwest::header::{HeaderMap, HeaderValue, ETAG, IF_NONE_MATCH, RANGE};
+use retry::Retry;
+use rewrite::Rewrite;
+use staging::staging_path;
+use stream::read_blocking;
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::{AsyncSeekExt, AsyncWrite, AsyncWriteExt},
+    sync::{
+        mpsc::{channel, Sender},
+        Semaphore,
+    },
+    task::JoinSet,
+};
+
+mod archive;
+pub mod auth;
+mod batch;
+mod blob;
+mod bundle;
+mod entry;
+mod error;
+mod etag;
+mod event;
+mod filter;
+mod handle;
+mod lfs;
+mod lock;
+mod observer;
+mod progress;
+pub mod provider;
+mod rate_limit;
+mod retry;
+mod rewrite;
+#[cfg(any(feature = "codecommit", feature = "s3"))]
+mod sigv4;
+mod staging;
+pub mod storage;
+mod stream;
+#[cfg(test)]
+mod stub;
+mod sync;
 
+pub use batch::{BatchBuilder, BatchDownloader};
+pub use entry::{Entry, EntryKind};
+pub use error::Error;
+pub use event::DownloadEvent;
+pub use handle::{CancellationToken, DownloadHandle};
+pub use lock::Lockfile;
+pub use observer::ProgressObserver;
+pub use progress::{JobProgress, ProgressAggregator};
+pub use provider::Provider;
+pub use rate_limit::RateLimit;
+use storage::LocalStorage;
+pub use storage::Storage;
+
+/// An entry of the remote tree
 #[derive(serde::Deserialize, Debug)]
-struct Node {
+pub struct Node {
     path: String,
-    size: Option<isize>,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    size: Option<u64>,
+    /// The git blob sha, gitlab calls it the id
+    #[serde(default, alias = "id")]
+    sha: Option<String>,
+    /// The git mode in octal, such as 100755
+    #[serde(default)]
+    mode: Option<String>,
 }
 
-#[derive(serde::Deserialize, Debug)]
-struct FileTree {
-    tree: Vec<Node>,
+/// A file changed between two commits, as answered by [`Provider::changes`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// Added or modified, with the git blob sha it has now when the forge tells it
+    Changed { path: String, sha: Option<String> },
+    /// Removed, a renamed file is removed from its old path and changed at the new one
+    Removed { path: String },
+}
+
+impl Node {
+    /// A file at `path`, relative to the repo root
+    pub fn blob(path: &str) -> Self {
+        Self {
+            path: path.into(),
+            kind: "blob".into(),
+            size: None,
+            sha: None,
+            mode: None,
+        }
+    }
+
+    /// With its size in bytes, so the download is sized up before it starts
+    pub fn size(mut self, size: u64) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// A directory at `path`, relative to the repo root
+    pub fn tree(path: &str) -> Self {
+        Self {
+            path: path.into(),
+            kind: "tree".into(),
+            size: None,
+            sha: None,
+            mode: None,
+        }
+    }
+}
+
+/// The repository coordinates handed to a [`Provider`]
+#[derive(Clone)]
+pub struct Remote {
+    user: String,
+    repo: String,
+    branch: String,
+    path: String,
+    token: Option<String>,
+}
+
+impl Remote {
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    pub fn repo(&self) -> &str {
+        &self.repo
+    }
+
+    pub fn branch(&self) -> &str {
+        &self.branch
+    }
+
+    /// The requested remote path, providers may list only the part of the tree below it
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
 pub struct Process {
     pub current: usize,
     pub all: usize,
+    /// The quota last reported by the server, when it reports one
+    pub rate_limit: Option<RateLimit>,
 }
 
 impl Process {
-    fn new(n: usize) -> Arc<Mutex<Self>> {
-        let this = Self { current: 0, all: n };
-        Arc::new(Mutex::new(this))
+    fn new(n: usize) -> Arc<tokio::sync::Mutex<Self>> {
+        let this = Self {
+            current: 0,
+            all: n,
+            rate_limit: None,
+        };
+        Arc::new(tokio::sync::Mutex::new(this))
     }
 
     fn deep_clone(&self) -> Self {
         Self {
             current: self.current,
             all: self.all,
+            rate_limit: self.rate_limit,
         }
     }
 
@@ -51,168 +200,3762 @@ impl Process {
     }
 }
 
-macro_rules! send_if_err {
-    ($tx: expr,$result: expr) => {
-        if let Err(err) = $result {
-            $tx.send(Err(err.to_string())).unwrap();
-            return;
+/// When the files are fetched as a single archive of the whole tree instead of one by one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tarball {
+    /// Always file by file
+    #[default]
+    Never,
+    /// As an archive when the requested path holds at least three quarters of the files
+    Auto,
+    /// As an archive whenever the provider serves one
+    Always,
+}
+
+/// What a download does when a file fails
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Stop at the first failed file and return its error
+    #[default]
+    FailFast,
+    /// Download the other files and list the failed ones in [`DownloadReport::failed`]
+    ContinueAndReport,
+}
+
+/// What a download does with files written to the same path, such as when it is
+/// [flattened](DownloaderBuilder::flatten)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// Fail with [`Error::Collision`] before anything is fetched
+    #[default]
+    Fail,
+    /// Keep the first of them in the tree's order and skip the others
+    KeepFirst,
+    /// Number the others, such as `init-2.lua` after `init.lua`
+    Rename,
+}
+
+/// What a download does with the symlinks of the tree, whose blob holds the path they point to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Recreate them as real symlinks, on Unix, the ones pointing out of the local path
+    /// are written as files holding their target, as git does without symlinks
+    #[default]
+    Link,
+    /// Leave them out of the download
+    Skip,
+    /// Write the file of the repo they point to in their place, a link pointing out of the
+    /// repo or to a directory fails
+    Materialize,
+    /// Write them as files holding their target
+    File,
+}
+
+/// What a download does with the files already at the paths it writes to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// Replace them
+    #[default]
+    Overwrite,
+    /// Leave them as they are and download the others
+    Skip,
+    /// Fail with [`Error::FileExists`] before anything is fetched
+    Error,
+    /// Replace them, keeping each one beside the new file as `<name>.bak`
+    Backup,
+}
+
+/// What a finished download did
+#[derive(Clone, Debug)]
+pub struct DownloadReport {
+    /// The ref the files were downloaded at, such as master when main didn't exist
+    pub branch: String,
+    /// How many files were requested
+    pub attempted: usize,
+    /// How many files were written
+    pub succeeded: usize,
+    /// How many local files were left as is, as they match the remote ones or the
+    /// [`OverwritePolicy`] skips them
+    pub up_to_date: usize,
+    /// The files that failed and why
+    pub failed: Vec<FailedFile>,
+    /// The local files a [sync](Downloader::sync) removed
+    pub deleted: Vec<PathBuf>,
+    /// The bytes of the written files
+    pub bytes: u64,
+    /// How long the download took, listing the tree included
+    pub elapsed: Duration,
+}
+
+/// What [`Downloader::verify`] found comparing the local files with the remote tree
+#[derive(Clone, Debug, Default)]
+pub struct Verification {
+    /// The remote files with nothing at their local path
+    pub missing: Vec<Entry>,
+    /// The remote files whose local file has another size or sha
+    pub modified: Vec<Entry>,
+    /// The local files missing from the remote tree, which a [sync](Downloader::sync) removes
+    pub extra: Vec<PathBuf>,
+    /// How many local files match the remote ones
+    pub intact: usize,
+}
+
+impl Verification {
+    /// Whether the local files are exactly the remote ones
+    pub fn is_intact(&self) -> bool {
+        self.missing.is_empty() && self.modified.is_empty() && self.extra.is_empty()
+    }
+}
+
+/// What a [sync](Downloader::sync) would change, as answered by [`Downloader::diff`]
+#[derive(Clone, Debug, Default)]
+pub struct Diff {
+    /// The remote files with nothing at their local path yet
+    pub added: Vec<Entry>,
+    /// The remote files whose local file differs
+    pub updated: Vec<FileDiff>,
+    /// The local files missing from the remote tree
+    pub removed: Vec<PathBuf>,
+}
+
+/// A file of a [`Diff`] whose local file differs from the remote one
+#[derive(Clone, Debug)]
+pub struct FileDiff {
+    pub entry: Entry,
+    /// Where the local file is
+    pub path: PathBuf,
+    /// The changes from the local file to the remote one, when asked for and both are text
+    pub unified: Option<String>,
+}
+
+/// A file of a [`DownloadReport`] that couldn't be downloaded
+#[derive(Clone, Debug)]
+pub struct FailedFile {
+    pub entry: Entry,
+    pub error: Arc<anyhow::Error>,
+}
+
+// the first and optionally the last byte wanted
+fn range(start: u64, end: Option<u64>) -> HeaderMap {
+    let end = end.map(|end| end.to_string()).unwrap_or_default();
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        RANGE,
+        HeaderValue::from_str(&format!("bytes={start}-{end}")).unwrap(),
+    );
+    headers
+}
+
+type ProcessHandler = Arc<dyn Fn(Process) + Send + Sync>;
+
+type EventHandler = Arc<dyn Fn(&DownloadEvent) + Send + Sync>;
+
+type ErrorHandler = Arc<dyn Fn(&Entry, &anyhow::Error) + Send + Sync>;
+type EntryFilter = Arc<dyn Fn(&Entry) -> bool + Send + Sync>;
+type DeleteGuard = Arc<dyn Fn(&Path) -> bool + Send + Sync>;
+type DestinationMap = Arc<dyn Fn(&Path) -> Option<PathBuf> + Send + Sync>;
+type Manifest = Arc<Mutex<BTreeMap<String, String>>>;
+type Sink = Arc<dyn Fn(&Entry, &Path) -> Pin<Box<dyn AsyncWrite + Send>> + Send + Sync>;
+
+// where a file is written until it is complete, such as init.lua.part
+pub(crate) fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+// where the backup policy keeps a replaced file, such as init.lua.bak
+pub(crate) fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    PathBuf::from(backup)
+}
+
+// whether a local file hashes to the remote blob, or has its size when the listing has
+// no sha
+async fn matches(storage: &dyn Storage, entry: &Entry, dst: &Path) -> Result<bool> {
+    // a link is told by where it points
+    if entry.kind == EntryKind::Symlink {
+        if let Ok(target) = fs::read_link(dst).await {
+            let sha = content_sha(target.as_os_str().as_encoded_bytes());
+            return Ok(entry.sha.as_ref() == Some(&sha));
+        }
+    }
+    let len = match storage.metadata(dst).await? {
+        Some(meta) => meta.size,
+        None => return Ok(false),
+    };
+    // another size settles it without reading the file
+    if entry.size.is_some_and(|size| size != len) {
+        return Ok(false);
+    }
+    let Some(sha) = &entry.sha else {
+        return Ok(entry.size.is_some());
+    };
+    let local = dst.to_path_buf();
+    Ok(tokio::task::spawn_blocking(move || blob_sha(&local)).await?? == *sha)
+}
+
+// such as 0o755 for 0o644, everyone allowed to read the file may run it too, the archive
+// keeps the modes of its files already
+#[cfg(unix)]
+async fn mark_executable(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut permissions = fs::metadata(path).await?.permissions();
+    let mode = permissions.mode();
+    permissions.set_mode(mode | (mode & 0o444) >> 2);
+    fs::set_permissions(path, permissions).await
+}
+
+#[cfg(not(unix))]
+async fn mark_executable(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+// io errors are told apart as the file that couldn't be written
+fn write_failed(path: &Path, err: anyhow::Error) -> anyhow::Error {
+    match err.downcast::<std::io::Error>() {
+        Ok(source) => Error::WriteFailed {
+            path: path.into(),
+            source,
+        }
+        .into(),
+        Err(err) => err,
+    }
+}
+
+// where a link in `dir` pointing to `target` leads, both relative to the same root, none
+// for an absolute target or one climbing out of the root
+fn resolve_link(dir: &Path, target: &str) -> Option<PathBuf> {
+    let mut resolved = PathBuf::new();
+    if Path::new(target).has_root() {
+        return None;
+    }
+    for component in dir.join(target).components() {
+        match component {
+            Component::Normal(part) => resolved.push(part),
+            Component::CurDir => {}
+            Component::ParentDir if resolved.pop() => {}
+            _ => return None,
+        }
+    }
+    Some(resolved)
+}
+
+// the url of every submodule by its path, as listed in the sections of a .gitmodules
+fn gitmodules(content: &str) -> HashMap<String, String> {
+    let mut urls = HashMap::new();
+    let (mut path, mut url) = (None, None);
+    for line in content.lines().map(str::trim).chain(["["]) {
+        if line.starts_with('[') {
+            if let (Some(path), Some(url)) = (path.take(), url.take()) {
+                urls.insert(path, url);
+            }
+            continue;
+        }
+        match line.split_once('=') {
+            Some((key, value)) if key.trim() == "path" => path = Some(value.trim().into()),
+            Some((key, value)) if key.trim() == "url" => url = Some(value.trim().into()),
+            _ => {}
+        }
+    }
+    urls
+}
+
+// the user and repo a submodule url names, such as https://github.com/user/repo.git or
+// git@github.com:user/repo.git, a relative one such as ../repo.git is taken from the repo
+// it is listed by
+fn submodule_repo(url: &str, remote: &Remote) -> Option<(String, String)> {
+    let mut parts: Vec<String> = vec![];
+    match url.starts_with("./") || url.starts_with("../") {
+        true => {
+            parts = vec![remote.user.clone(), remote.repo.clone()];
+            for part in url.split('/') {
+                match part {
+                    "." | "" => {}
+                    ".." => drop(parts.pop()),
+                    part => parts.push(part.into()),
+                }
+            }
         }
-        $result.unwrap()
+        false => {
+            let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+            let (_, path) = rest.split_once(['/', ':'])?;
+            parts.extend(
+                path.split('/')
+                    .filter(|part| !part.is_empty())
+                    .map(String::from),
+            );
+        }
+    }
+    match parts.as_slice() {
+        [.., user, repo] => Some((user.clone(), repo.trim_end_matches(".git").into())),
+        _ => None,
+    }
+}
+
+// only a file, a range of it or the news that it is unchanged is written, a missing file
+// is told as such and the other answers fail it, server errors to be retried
+fn checked(res: reqwest::Response, path: &str) -> Result<reqwest::Response> {
+    let status = res.status();
+    if status.is_success() || status == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(res);
+    }
+    if status == reqwest::StatusCode::NOT_FOUND {
+        return Err(Error::PathNotFound(path.into()).into());
+    }
+    if let Some(err) = Error::rate_limited(&res) {
+        return Err(err.into());
+    }
+    Ok(res.error_for_status()?)
+}
+
+// such as init-2.lua for init.lua
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}-{n}.{}", ext.to_string_lossy()),
+        None => format!("{stem}-{n}"),
     };
+    path.with_file_name(name)
 }
 
-impl FileTree {
-    async fn download(&self, downloader: Arc<Downloader>, tx: Sender<Result<Process, String>>) {
-        let tasks: Vec<_> = self
-            .tree
-            .iter()
-            .filter(|node| node.size.is_some())
-            .map(|node| Arc::new(PathBuf::from(&node.path)))
-            .filter(|path| {
-                let src = PathBuf::from(&downloader.remote_path);
-                path.starts_with(src)
-            })
-            .collect();
+struct AbortOnDrop(tokio::task::JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Cloned cheaply, the clones share the http client, the etags, the memory budget and the
+/// [`DownloadHandle`], so one configured downloader serves repeated or parallel downloads
+#[derive(Clone)]
+pub struct Downloader {
+    client: reqwest::Client,
+    remote: Remote,
+    provider: Arc<dyn Provider>,
+    fallback: Option<Mirror>,
+    auth: Option<Arc<dyn TokenSource>>,
+    remote_path: String,
+    // the remote paths downloaded together below the remote path, when there are several
+    paths: Vec<String>,
+    local_path: PathBuf,
+    max_concurrency: usize,
+    // the permits shared by the jobs of a batch instead of the max concurrency of each
+    permits: Option<Arc<Semaphore>>,
+    retry: Retry,
+    file_timeout: Option<Duration>,
+    deadline: Option<Duration>,
+    resume: bool,
+    segments: Option<(u64, usize)>,
+    etags: Option<Arc<ETags>>,
+    cache: Option<PathBuf>,
+    update: bool,
+    force: bool,
+    tarball: Tarball,
+    // the permits, in KiB, and their total, transfers wait for as many as their size
+    memory_budget: Option<(Arc<Semaphore>, u32)>,
+    max_total_size: Option<u64>,
+    max_files: Option<usize>,
+    dry_run: bool,
+    sink: Option<Sink>,
+    // the storage the sink writes into, the local filesystem when none is given
+    storage: Option<Arc<dyn Storage>>,
+    bundle: Option<(bundle::Format, PathBuf)>,
+    // where the manifest of a content addressed download goes and its entries
+    manifest: Option<(PathBuf, Manifest)>,
+    flatten: bool,
+    collision: CollisionPolicy,
+    rewrites: Vec<Rewrite>,
+    map_destination: Option<DestinationMap>,
+    // the files of the run written elsewhere than their path below the local path
+    destinations: Arc<HashMap<String, PathBuf>>,
+    overwrite: OverwritePolicy,
+    symlinks: SymlinkPolicy,
+    // whether git lfs pointers are written as they are instead of the objects
+    lfs_pointers: bool,
+    lockfile: Option<PathBuf>,
+    // the lockfile an update compares with
+    locked: Option<Arc<Lockfile>>,
+    // the commit a download of the changes since starts from, and the files removed since
+    // when the forge told them
+    since: Option<String>,
+    removed: Option<Vec<String>>,
+    // whether the run removes the local files missing from the remote tree
+    sync: bool,
+    confirm_delete: Option<DeleteGuard>,
+    staged: bool,
+    // where the files of a staged run are written before they are moved into place
+    staging: Option<PathBuf>,
+    submodules: bool,
+    // where the submodules of the run went, left alone by a sync, and their reports, which
+    // are added to the run's
+    modules: Vec<PathBuf>,
+    nested: Arc<Mutex<Vec<DownloadReport>>>,
+    // when the deadline started running, that of the outer run for a submodule
+    deadline_start: Option<Instant>,
+    rate_limit: Arc<Mutex<Option<RateLimit>>>,
+    // the quota last reported for each token, none for anonymous requests
+    quotas: Arc<Mutex<HashMap<Option<String>, RateLimit>>>,
+    process_handler: ProcessHandler,
+    event_handler: EventHandler,
+    error_handler: ErrorHandler,
+    error_policy: ErrorPolicy,
+    observers: Vec<Arc<dyn ProgressObserver>>,
+    filters: Vec<EntryFilter>,
+    handle: DownloadHandle,
+    // the local files being written
+    in_flight: Arc<Mutex<HashSet<PathBuf>>>,
+    // the commit the files are fetched at instead of the ref, pinned for a lockfile
+    commit: Option<String>,
+}
+
+impl Downloader {
+    const USER_AGENT:&'static str="Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/109.0.5410.0 Safari/537.36";
+    const MAX_CONCURRENCY: usize = 16;
+
+    // the client requests are sent with when none is given
+    pub(crate) fn default_client() -> reqwest::ClientBuilder {
+        reqwest::ClientBuilder::new().user_agent(Self::USER_AGENT)
+    }
+
+    fn spawn_tasks(
+        downloader: Arc<Downloader>,
+        tasks: Vec<Entry>,
+        tx: Sender<DownloadEvent>,
+    ) -> JoinSet<()> {
+        let tasks: Vec<_> = tasks.into_iter().map(Arc::new).collect();
         let process = Process::new(tasks.len());
-        tasks.iter().for_each(|path| {
-            let src = PathBuf::from(&downloader.remote_path);
-            let dst = PathBuf::from(&downloader.local_path);
-            let path = path.clone();
+        let semaphore = match &downloader.permits {
+            Some(permits) => permits.clone(),
+            None => Arc::new(Semaphore::new(downloader.max_concurrency)),
+        };
+        let mut set = JoinSet::new();
+        tasks.iter().for_each(|entry| {
+            let entry = entry.clone();
             let tx = tx.clone();
             let downloader = downloader.clone();
             let process = process.clone();
-            tokio::spawn(async move {
-                // src is remote path, such as nvim/init.lua
-                // dst is local path such as src
-                // path is the exact remote path, on the situation of single file, path equals with src
-                // the final dst is the download path, such as src/init.lua
-                let dst = dst.join(path.strip_prefix(&src).unwrap());
-                let dst_dir = dst.parent().unwrap();
-                create_dir_all(dst_dir).unwrap();
-                send_if_err!(
-                    tx,
-                    downloader
-                        .download_single(
-                            path.as_os_str().to_str().unwrap(),
-                            dst.to_str().unwrap().trim_end_matches("/")
-                        )
-                        .await
-                );
-                let mut lock = process.lock().unwrap();
+            let semaphore = semaphore.clone();
+            set.spawn(async move {
+                // the receiver is only gone with the download, which aborts the task anyway
+                let queued = (*entry).clone();
+                tx.send(DownloadEvent::FileQueued { entry: queued })
+                    .await
+                    .ok();
+                let observers = &downloader.observers;
+                let written = match downloader.download_file(&entry, &semaphore).await {
+                    Ok(bytes) => bytes,
+                    Err(err) => {
+                        observers.iter().for_each(|o| o.on_error(&entry, &err));
+                        let entry = (*entry).clone();
+                        let error = Arc::new(err);
+                        tx.send(DownloadEvent::FileFailed { entry, error })
+                            .await
+                            .ok();
+                        return;
+                    }
+                };
+                // sent under the lock, so the updates arrive in order
+                let mut lock = process.lock().await;
                 lock.done();
+                lock.rate_limit = *downloader.rate_limit.lock().unwrap();
                 let process = lock.deep_clone();
-                tx.send(Ok(process)).unwrap();
+                let event = match written {
+                    Some(bytes) => {
+                        observers
+                            .iter()
+                            .for_each(|o| o.on_file_done(&entry, process));
+                        DownloadEvent::FileCompleted {
+                            entry: (*entry).clone(),
+                            bytes,
+                            process,
+                            would_download: true,
+                        }
+                    }
+                    None => {
+                        observers
+                            .iter()
+                            .for_each(|o| o.on_file_up_to_date(&entry, process));
+                        DownloadEvent::FileUpToDate {
+                            entry: (*entry).clone(),
+                            process,
+                        }
+                    }
+                };
+                tx.send(event).await.ok();
             });
         });
+        set
     }
-}
 
-pub struct Downloader {
-    user: String,
-    repo: String,
-    branch: String,
-    remote_path: String,
-    local_path: String,
-    process_handler: fn(Process),
-}
+    // src is remote path, such as nvim/init.lua
+    // dst is local path such as src
+    // path is the exact remote path, on the situation of single file, path equals with src
+    // the final dst is the download path, such as src/init.lua
+    fn destination(&self, path: &str) -> Result<PathBuf> {
+        if let Some(dst) = self.destinations.get(path) {
+            return Ok(dst.clone());
+        }
+        let rest = Path::new(path)
+            .strip_prefix(&self.remote_path)
+            .map_err(|_| anyhow!("{path} isn't below {}", self.remote_path))?;
+        Ok(match rest.as_os_str().is_empty() {
+            true => self.local_path.clone(),
+            false => self.local_path.join(rest),
+        })
+    }
 
-impl Downloader {
-    const USER_AGENT:&'static str="Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/109.0.5410.0 Safari/537.36";
+    // where a file is written in a staged run, the tree below the local path is mirrored
+    // in the staging directory, and the files mapped elsewhere are kept apart
+    fn staged(&self, dst: &Path) -> PathBuf {
+        let Some(staging) = &self.staging else {
+            return dst.into();
+        };
+        match dst.strip_prefix(&self.local_path) {
+            Ok(rest) if !rest.as_os_str().is_empty() => staging.join(rest),
+            _ => staging
+                .join(".elsewhere")
+                .join(path_sha(&dst.to_string_lossy())),
+        }
+    }
 
-    async fn download_single(&self, path: &str, dst: &str) -> Result<()> {
-        let url = format!(
-            "https://raw.githubusercontent.com/{}/{}/{}/{path}",
-            &self.user, &self.repo, &self.branch
-        );
-        let client = reqwest::ClientBuilder::new()
-            .user_agent(Self::USER_AGENT)
-            .build()?;
-        let res = client.get(url).send().await?.text().await?;
-        let mut file = File::create(dst)?;
-        file.write_all(res.as_bytes())?;
-        Ok(())
+    // waits for its turn, then downloads a file and answers the bytes written, none for a
+    // local file left as is or told unchanged
+    async fn download_file(&self, entry: &Entry, semaphore: &Semaphore) -> Result<Option<u64>> {
+        // bounds the simultaneous requests
+        let _permit = semaphore.acquire().await?;
+        let _budget = match &self.memory_budget {
+            Some((budget, total)) => {
+                let kib = entry.size.unwrap_or_default().div_ceil(1024);
+                let kib = kib.min(*total as u64) as u32;
+                Some(budget.acquire_many(kib).await?)
+            }
+            None => None,
+        };
+        self.handle.unpaused().await;
+        let dst = self.destination(&entry.path)?;
+        self.observers.iter().for_each(|o| o.on_file_start(entry));
+        if self.left_as_is(entry, &dst).await? {
+            return Ok(None);
+        }
+        if self.sink.is_some() {
+            return self.download_with_retries(entry, &dst).await;
+        }
+        if self.manifest.is_some() {
+            return self.download_object(entry).await.map(Some);
+        }
+        let target = self.staged(&dst);
+        let part = part_path(&target);
+        self.in_flight.lock().unwrap().insert(part.clone());
+        let res = async {
+            if let Some(dir) = target.parent() {
+                fs::create_dir_all(dir).await?;
+            }
+            match entry.kind {
+                EntryKind::Symlink if self.symlinks != SymlinkPolicy::File => {
+                    self.download_link(entry, &dst).await.map(|()| true)
+                }
+                _ => self.download_cached(entry, &dst).await,
+            }
+        }
+        .await;
+        self.in_flight.lock().unwrap().remove(&part);
+        if res.is_err() && !self.resume {
+            fs::remove_file(&part).await.ok();
+        }
+        // the file of the last run was left as is, the etag telling it unchanged
+        if !res.map_err(|err| write_failed(&dst, err))? {
+            return Ok(None);
+        }
+        let pointer = entry.size.is_none_or(|size| size <= lfs::Pointer::MAX_SIZE);
+        if entry.kind == EntryKind::File && pointer && !self.lfs_pointers {
+            self.resolve_lfs(&target).await?;
+        }
+        if entry.is_executable() {
+            mark_executable(&target)
+                .await
+                .map_err(|err| write_failed(&dst, err.into()))?;
+        }
+        Ok(Some(
+            fs::symlink_metadata(&target)
+                .await
+                .map(|meta| meta.len())
+                .unwrap_or_default(),
+        ))
     }
 
-    pub async fn download(self) -> Result<()> {
-        let url = format!(
-            "https://api.github.com/repos/{}/{}/git/trees/{}?recursive=1",
-            &self.user, &self.repo, &self.branch
-        );
-        let client = reqwest::ClientBuilder::new()
-            .user_agent(Self::USER_AGENT)
-            .build()?;
-        let res = client.get(url).send().await.unwrap().text().await.unwrap();
-        let file_tree: FileTree = serde_json::from_str(&res)
-            .map_err(|_| anyhow!("Are you sure the repo really exists?"))?;
+    // the content of a remote file held in memory, such as of a small one
+    async fn content(&self, path: &str) -> Result<provider::Bytes> {
+        let res = self.fetch(path, &HeaderMap::new()).await?;
+        let content = checked(res, path)?.bytes().await?;
+        match self.provider.buffered() {
+            true => self.provider.decode(content),
+            false => Ok(content),
+        }
+    }
 
-        let (tx, mut rx) = channel::<Result<Process, String>>(5);
+    // whether a local file is the remote one, a file resolved from git lfs is compared with
+    // the object its pointer names, as the listing tells the size and sha of the pointer
+    async fn matches_remote(&self, entry: &Entry, dst: &Path) -> Result<bool> {
+        let storage = self.storage();
+        if matches(&*storage, entry, dst).await? {
+            return Ok(true);
+        }
+        let pointer = entry.size.is_none_or(|size| size <= lfs::Pointer::MAX_SIZE);
+        if entry.kind != EntryKind::File || !pointer || self.lfs_pointers {
+            return Ok(false);
+        }
+        let len = match storage.metadata(dst).await? {
+            Some(meta) => meta.size,
+            None => return Ok(false),
+        };
+        // a pointer that can't be fetched, such as of a file removed since, leaves the local
+        // file told apart from it
+        let Ok(content) = self.content(&entry.path).await else {
+            return Ok(false);
+        };
+        match lfs::Pointer::parse(&content) {
+            Some(pointer) if pointer.size == len => {
+                let local = dst.to_path_buf();
+                let oid = tokio::task::spawn_blocking(move || lfs_oid(&local)).await??;
+                Ok(oid == pointer.oid)
+            }
+            _ => Ok(false),
+        }
+    }
 
-        let me = Arc::new(self);
+    // a git lfs pointer written in place of a file is replaced by the object it points to,
+    // it stays as is when the forge serves no lfs
+    async fn resolve_lfs(&self, target: &Path) -> Result<()> {
+        let meta = fs::symlink_metadata(target).await?;
+        if !meta.is_file() || meta.len() > lfs::Pointer::MAX_SIZE {
+            return Ok(());
+        }
+        let Some(pointer) = lfs::Pointer::parse(&fs::read(target).await?) else {
+            return Ok(());
+        };
+        let remote = self.remote().await?;
+        let Some(req) = self.provider.lfs(&self.client, &remote) else {
+            return Ok(());
+        };
+        let (href, headers) = lfs::locate(req, &pointer).await?;
+        let part = part_path(target);
+        let res = async {
+            let mut res = self
+                .client
+                .get(href)
+                .headers(headers)
+                .send()
+                .await?
+                .error_for_status()?;
+            let mut file = File::create(&part).await?;
+            let mut written = 0;
+            while let Some(chunk) = res.chunk().await? {
+                self.handle.unpaused().await;
+                file.write_all(&chunk).await?;
+                written += chunk.len() as u64;
+            }
+            file.shutdown().await?;
+            let fetched = part.clone();
+            let oid = tokio::task::spawn_blocking(move || lfs_oid(&fetched)).await??;
+            if written != pointer.size || oid != pointer.oid {
+                return Err(anyhow!(
+                    "The lfs object {} arrived as {oid} of {written} bytes",
+                    pointer.oid
+                ));
+            }
+            // the pointer was put in place already, it is replaced rather than backed up
+            fs::rename(&part, target).await?;
+            Ok(())
+        }
+        .await;
+        if res.is_err() {
+            fs::remove_file(&part).await.ok();
+        }
+        res
+    }
+
+    // a symlink is recreated as a link when it points below the local path, or replaced by
+    // the file it points to, otherwise it is written as a file holding its target
+    async fn download_link(&self, entry: &Entry, dst: &Path) -> Result<()> {
+        let target = String::from_utf8(self.content(&entry.path).await?.to_vec())?;
+        if self.symlinks == SymlinkPolicy::Materialize {
+            let dir = Path::new(&entry.path).parent().unwrap_or(Path::new(""));
+            let Some(path) = resolve_link(dir, &target) else {
+                return Err(anyhow!("{} points out of the repo to {target}", entry.path));
+            };
+            let file = Entry {
+                path: path.to_string_lossy().into_owned(),
+                kind: EntryKind::File,
+                size: None,
+                sha: None,
+                mode: None,
+            };
+            return self.download_with_retries(&file, dst).await.map(drop);
+        }
+        // a link downloaded as the local path itself points out of it
+        let within = match dst.strip_prefix(&self.local_path) {
+            Ok(rest) if !rest.as_os_str().is_empty() => {
+                resolve_link(rest.parent().unwrap_or(Path::new("")), &target).is_some()
+            }
+            _ => false,
+        };
+        let staged = self.staged(dst);
+        let part = part_path(&staged);
+        fs::remove_file(&part).await.ok();
+        match within && cfg!(unix) {
+            #[cfg(unix)]
+            true => fs::symlink(&target, &part).await?,
+            _ => fs::write(&part, &target).await?,
+        }
+        self.place(&part, &staged).await?;
+        Ok(())
+    }
+
+    // the file is stored as its blob under objects/<sha[..2]>/<sha> and listed in the
+    // manifest, a blob stored before, such as by another repo, is fetched no more
+    async fn download_object(&self, entry: &Entry) -> Result<u64> {
+        let objects = self.local_path.join("objects");
+        let object = |sha: &str| objects.join(&sha[..2]).join(sha);
+        let known = entry.sha.clone().filter(|sha| sha.len() > 2);
+        let stored = match &known {
+            Some(sha) if !self.force => fs::metadata(object(sha)).await.ok(),
+            _ => None,
+        };
+        let (sha, size) = match (known, stored) {
+            (Some(sha), Some(meta)) if meta.is_file() => (sha, meta.len()),
+            (known, _) => {
+                let fetched = objects.join(path_sha(&entry.path));
+                let part = part_path(&fetched);
+                self.in_flight.lock().unwrap().insert(part.clone());
+                let res = async {
+                    fs::create_dir_all(&objects).await?;
+                    self.download_with_retries(entry, &fetched).await?;
+                    let sha = match known {
+                        Some(sha) => sha,
+                        None => {
+                            let fetched = fetched.clone();
+                            tokio::task::spawn_blocking(move || blob_sha(&fetched)).await??
+                        }
+                    };
+                    let dst = object(&sha);
+                    if let Some(dir) = dst.parent() {
+                        fs::create_dir_all(dir).await?;
+                    }
+                    fs::rename(&fetched, &dst).await?;
+                    Ok::<_, anyhow::Error>((sha, fs::metadata(&dst).await?.len()))
+                }
+                .await;
+                self.in_flight.lock().unwrap().remove(&part);
+                res.map_err(|err| write_failed(&fetched, err))?
+            }
+        };
+        if let Some((_, entries)) = &self.manifest {
+            let path = filter::relative(&entry.path, &self.remote_path);
+            entries.lock().unwrap().insert(path.into(), sha);
+        }
+        Ok(size)
+    }
 
-        file_tree.download(me.clone(), tx).await;
+    // the remote carrying a token fresh from the token source
+    async fn remote(&self) -> Result<Remote> {
+        let mut remote = self.remote.clone();
+        if let Some(commit) = &self.commit {
+            remote.branch.clone_from(commit);
+        }
+        if let Some(auth) = &self.auth {
+            remote.token = Some(auth.token(&self.client, &self.remote).await?);
+        }
+        Ok(remote)
+    }
 
+    // headers such as the range or the etag are sent along with the provider's own
+    async fn fetch(&self, path: &str, headers: &HeaderMap) -> Result<reqwest::Response> {
+        let mut attempt = 0;
         loop {
-            let process = rx
-                .recv()
-                .await
-                .map_err(|_| anyhow!("Are you sure the target name is right?"))?
-                .unwrap();
-            (me.process_handler)(process);
-            if process.is_over() {
-                return Ok(());
+            let remote = self.remote().await?;
+            // once the quota of the token is used up, its files wait for it to be refilled,
+            // another token of the source is sent right away
+            let wait = self
+                .quotas
+                .lock()
+                .unwrap()
+                .get(&remote.token)
+                .and_then(|limit| limit.wait());
+            if let Some(wait) = wait {
+                tokio::time::sleep(wait).await;
+            }
+            let req = self.provider.raw(&self.client, &remote, path);
+            let req = req.headers(headers.clone());
+            let res = req.send().await?;
+            let rate_limit = RateLimit::from_headers(res.headers());
+            if let Some(limit) = rate_limit {
+                *self.rate_limit.lock().unwrap() = rate_limit;
+                let mut quotas = self.quotas.lock().unwrap();
+                quotas.insert(remote.token.clone(), limit);
+            }
+            if !matches!(res.status().as_u16(), 403 | 429) {
+                return Ok(res);
+            }
+            // secondary rate limits of bursty downloads are waited out with the same token
+            let backoff = retry_after(res.headers());
+            // a rate limited token is rotated out when the token source has others left,
+            // otherwise the request is sent again once the quota is refilled
+            if let (None, Some(auth), Some(token)) = (backoff, &self.auth, &remote.token) {
+                if auth.rejected(token) {
+                    continue;
+                }
+            }
+            let exhausted = rate_limit.is_some_and(|limit| limit.wait().is_some());
+            if backoff.is_none() && !exhausted {
+                return Ok(res);
+            }
+            // the waits are bounded, a quota still used up once refilled, or a server asking
+            // to back off over and over, fails the file
+            attempt += 1;
+            if attempt >= rate_limit::ATTEMPTS {
+                return Err(Error::still_limited(rate_limit, backoff).into());
+            }
+            if let Some(wait) = backoff {
+                tokio::time::sleep(wait).await;
             }
         }
     }
-}
 
-#[derive(Default)]
-pub struct DownloaderBuilder {
-    user: String,
-    repo: String,
-    branch: Option<String>,
-    remote_path: String,
-    local_path: Option<String>,
-    process_handler: Option<fn(Process)>,
-}
+    // answers the bytes written by this attempt, none for a file the server told unchanged
+    async fn download_single(&self, entry: &Entry, dst: &Path) -> Result<Option<u64>> {
+        let path = entry.path.as_str();
+        // a sink has no file to resume, compare or write at offsets
+        let local = self.sink.is_none();
+        // written aside and moved into place once complete, so a cut off file is never
+        // taken for a whole one
+        let target = self.staged(dst);
+        let part = part_path(&target);
+        // a shorter file left by an interrupted run is resumed where it stopped
+        let offset = match (
+            local && self.resume && !self.force && !self.provider.buffered(),
+            entry.size,
+        ) {
+            (true, Some(size)) => self
+                .storage()
+                .metadata(&part)
+                .await?
+                .map(|meta| meta.size)
+                .filter(|&len| len > 0 && len < size),
+            _ => None,
+        };
+        let etag = match (&self.etags, offset) {
+            (Some(etags), None) if local && !self.force => etags.take(dst),
+            _ => None,
+        };
+        if let (Some((threshold, segments)), Some(size), None, None) =
+            (self.segments, entry.size, offset, &etag)
+        {
+            let segmented = local && size >= threshold && !self.provider.buffered();
+            if segmented && self.download_segmented(path, &part, size, segments).await? {
+                self.place(&part, &target).await?;
+                return Ok(Some(size));
+            }
+        }
+        let mut headers = match offset {
+            Some(offset) => range(offset, None),
+            None => HeaderMap::new(),
+        };
+        if let Some(etag) = etag
+            .as_deref()
+            .and_then(|etag| HeaderValue::from_str(etag).ok())
+        {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
+        let res = self.fetch(path, &headers).await;
+        let failed = res.as_ref().map_or(true, |res| {
+            let status = res.status();
+            !status.is_success() && status != reqwest::StatusCode::NOT_MODIFIED
+        });
+        let (mut res, decode) = match &self.fallback {
+            Some(fallback) if failed => {
+                // the mirror serves the commit the ref resolved to, as the provider would
+                let remote = self.remote().await?;
+                let res = fallback.raw(&self.client, &remote, path).send().await?;
+                (checked(res, path)?, false)
+            }
+            _ => {
+                let res = res?;
+                // the part no longer fits the remote file, which is fetched again whole
+                if offset.is_some() && res.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+                    fs::remove_file(&part).await?;
+                    return Box::pin(self.download_single(entry, dst)).await;
+                }
+                (checked(res, path)?, self.provider.buffered())
+            }
+        };
+        // the file left by the last run is unchanged
+        if res.status() == reqwest::StatusCode::NOT_MODIFIED {
+            if let (Some(etags), Some(etag)) = (&self.etags, &etag) {
+                etags.set(dst, etag);
+            }
+            return Ok(None);
+        }
+        let new_etag = match failed {
+            true => None,
+            false => res.headers().get(ETAG).and_then(|etag| etag.to_str().ok()),
+        }
+        .map(str::to_string);
+        // servers ignoring the range answer the whole file, which is written from scratch
+        let resumed = offset.is_some() && res.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+        // written as raw bytes, so images, fonts and archives survive
+        let mut file: Pin<Box<dyn AsyncWrite + Send>> = match (&self.sink, resumed) {
+            (Some(sink), _) => sink(entry, dst),
+            (None, true) => Box::pin(OpenOptions::new().append(true).open(&part).await?),
+            // a part kept to be resumed is written as it arrives, the storage would discard it
+            (None, false) if self.resume => Box::pin(File::create(&part).await?),
+            (None, false) => storage::writer(self.storage(), &part),
+        };
+        let mut written = 0;
+        match decode {
+            true => {
+                let content = self.provider.decode(res.bytes().await?)?;
+                file.write_all(&content).await?;
+                written += content.len() as u64;
+            }
+            // streamed chunk by chunk, memory stays bounded by the chunk instead of the file size
+            false => {
+                while let Some(chunk) = res.chunk().await? {
+                    self.handle.unpaused().await;
+                    file.write_all(&chunk).await?;
+                    written += chunk.len() as u64;
+                }
+            }
+        }
+        file.shutdown().await?;
+        if !local {
+            return Ok(Some(written));
+        }
+        // the remote file may have changed since the part was written, a resumed file not
+        // matching the listed sha is fetched again whole
+        if let (true, Some(sha)) = (resumed, &entry.sha) {
+            let fetched = part.clone();
+            if tokio::task::spawn_blocking(move || blob_sha(&fetched)).await?? != *sha {
+                fs::remove_file(&part).await?;
+                return Box::pin(self.download_single(entry, dst)).await;
+            }
+        }
+        self.place(&part, &target).await?;
+        if let (Some(etags), Some(etag)) = (&self.etags, &new_etag) {
+            etags.set(dst, etag);
+        }
+        Ok(Some(written))
+    }
 
-impl DownloaderBuilder {
-    pub fn new(user: &str, repo: &str, remote: &str) -> Self {
-        Self {
-            user: user.into(),
-            repo: repo.into(),
-            remote_path: remote.into(),
-            ..Default::default()
+    // large files are fetched as byte ranges in parallel, each written at its offset,
+    // returns false when the server doesn't serve ranges
+    async fn download_segmented(
+        &self,
+        path: &str,
+        dst: &Path,
+        size: u64,
+        segments: usize,
+    ) -> Result<bool> {
+        let file = File::create(dst).await?;
+        file.set_len(size).await?;
+        let step = size.div_ceil(segments as u64);
+        let ranges = (0..size).step_by(step as usize).map(|start| {
+            let end = (start + step).min(size) - 1;
+            async move {
+                let res = self.fetch(path, &range(start, Some(end))).await?;
+                if res.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+                    return Ok(false);
+                }
+                let mut res = res.error_for_status()?;
+                let mut file = OpenOptions::new().write(true).open(dst).await?;
+                file.seek(SeekFrom::Start(start)).await?;
+                while let Some(chunk) = res.chunk().await? {
+                    self.handle.unpaused().await;
+                    file.write_all(&chunk).await?;
+                }
+                file.flush().await?;
+                Ok::<_, anyhow::Error>(true)
+            }
+        });
+        let ranged = try_join_all(ranges).await?;
+        Ok(ranged.into_iter().all(|ranged| ranged))
+    }
+
+    // whether the files are written to their destinations on disk, rather than a sink, an
+    // archive or the objects of a content addressed layout
+    fn on_disk(&self) -> bool {
+        self.sink.is_none() && self.bundle.is_none() && self.manifest.is_none()
+    }
+
+    // whether the files are kept in a storage, the local filesystem or another one, whose
+    // files tell what is there already
+    fn stored(&self) -> bool {
+        let sink = self.sink.is_none() || self.storage.is_some();
+        sink && self.bundle.is_none() && self.manifest.is_none()
+    }
+
+    fn storage(&self) -> Arc<dyn Storage> {
+        match &self.storage {
+            Some(storage) => storage.clone(),
+            None => Arc::new(LocalStorage),
         }
     }
 
-    pub fn branch(mut self, branch: &str) -> Self {
-        self.branch = Some(branch.into());
-        self
+    // a local file is left as is when skipped by the overwrite policy, or in update mode
+    // when it matches the remote one
+    async fn left_as_is(&self, entry: &Entry, dst: &Path) -> Result<bool> {
+        if !self.stored() {
+            return Ok(false);
+        }
+        let stored = self.storage().metadata(dst).await?;
+        if self.overwrite == OverwritePolicy::Skip && stored.is_some() {
+            return Ok(true);
+        }
+        // the lockfile tells the files unchanged since without reading them
+        if let (Some(locked), Some(sha), false) = (&self.locked, &entry.sha, self.force) {
+            let path = filter::relative(&entry.path, &self.remote_path);
+            if locked.files.get(path) == Some(sha) && stored.is_some() {
+                return Ok(true);
+            }
+        }
+        if !self.update || self.force {
+            return Ok(false);
+        }
+        match self.on_disk() {
+            true => self.matches_remote(entry, dst).await,
+            // another storage only tells the size of its files
+            false => Ok(stored.is_some_and(|meta| entry.size == Some(meta.size))),
+        }
     }
 
-    pub fn local_path(mut self, local: &str) -> Self {
-        let remote = PathBuf::from(&self.remote_path);
-        let name = remote.file_name().unwrap().to_str().unwrap().to_string();
-        let local = PathBuf::from(local);
-        self.local_path = Some(local.join(name).to_str().unwrap().to_string());
-        self
+    // moves a complete file into place, the file it replaces is kept as its backup with the
+    // backup policy, in a staged run that is left to the move out of the staging directory
+    async fn place(&self, part: &Path, target: &Path) -> std::io::Result<()> {
+        let backup = self.overwrite == OverwritePolicy::Backup && self.staging.is_none();
+        if backup && fs::symlink_metadata(target).await.is_ok() {
+            fs::rename(target, backup_path(target)).await?;
+        }
+        fs::rename(part, target).await
     }
 
-    pub fn on_process(mut self, f: fn(Process)) -> Self {
-        self.process_handler = Some(f);
-        self
+    // blobs already in the cache are copied from it, the others are downloaded and added,
+    // answers false for a file the server told unchanged
+    async fn download_cached(&self, entry: &Entry, dst: &Path) -> Result<bool> {
+        let blob = match (&self.cache, &entry.sha) {
+            (Some(cache), Some(sha)) if sha.len() > 2 => cache.join(&sha[..2]).join(&sha[2..]),
+            _ => return Ok(self.download_with_retries(entry, dst).await?.is_some()),
+        };
+        let target = self.staged(dst);
+        // a forced download refreshes the blob rather than trusting it
+        if !self.force && fs::metadata(&blob).await.is_ok_and(|meta| meta.is_file()) {
+            let part = part_path(&target);
+            fs::copy(&blob, &part).await?;
+            self.place(&part, &target).await?;
+            return Ok(true);
+        }
+        if self.download_with_retries(entry, dst).await?.is_none() {
+            return Ok(false);
+        }
+        // copied aside first, so an interrupted copy is never taken for the blob
+        if let Some(dir) = blob.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        let partial = blob.with_extension("partial");
+        fs::copy(&target, &partial).await?;
+        fs::rename(&partial, &blob).await?;
+        Ok(true)
     }
 
-    pub fn build(self) -> Downloader {
-        let path = PathBuf::from(&self.remote_path);
-        let name = path.file_name().unwrap().to_str().unwrap().to_string();
-        Downloader {
-            user: self.user,
-            repo: self.repo,
-            remote_path: self.remote_path,
-            branch: self.branch.unwrap_or("main".into()),
-            local_path: self.local_path.unwrap_or(name),
-            process_handler: self.process_handler.unwrap_or(|_| {}),
+    async fn download_with_retries(&self, entry: &Entry, dst: &Path) -> Result<Option<u64>> {
+        let mut attempt = 0;
+        loop {
+            let res = match self.file_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, self.download_single(entry, dst))
+                    .await
+                    .unwrap_or_else(|elapsed| {
+                        let err = anyhow::Error::from(elapsed);
+                        Err(err.context(format!("No response within {timeout:?}")))
+                    }),
+                None => self.download_single(entry, dst).await,
+            };
+            match res {
+                Err(err) if attempt + 1 < self.retry.attempts && Retry::is_transient(&err) => {
+                    tokio::time::sleep(self.retry.delay(attempt)).await;
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    // whether the wanted files are better fetched as one archive of the whole tree
+    fn use_archive(&self, tasks: &[Entry], blobs: usize) -> bool {
+        let wanted = tasks.len();
+        match self.tarball {
+            // the archive is unpacked to the paths of the files on disk
+            _ if self.sink.is_some() || self.manifest.is_some() => false,
+            // the links of the archive would be recreated wherever they point
+            _ if tasks.iter().any(|entry| entry.kind == EntryKind::Symlink) => false,
+            _ if !self.destinations.is_empty() || self.staging.is_some() => false,
+            // the archive overwrites whatever is in the way, identical files included
+            // the changes are only part of the tree
+            _ if self.update || self.locked.is_some() || self.removed.is_some() => false,
+            _ if self.overwrite != OverwritePolicy::Overwrite => false,
+            Tarball::Never => false,
+            Tarball::Always => true,
+            Tarball::Auto => wanted * 4 >= blobs * 3,
+        }
+    }
+
+    // the archive is extracted while it streams in
+    // answers how many files of how many bytes are written
+    async fn download_archive(
+        &self,
+        req: reqwest::RequestBuilder,
+        tasks: &[Entry],
+    ) -> Result<(usize, u64)> {
+        let res = req.send().await?.error_for_status()?;
+        let all = tasks.len();
+        let wanted: HashSet<_> = tasks.iter().map(|entry| entry.path.clone()).collect();
+        let remote_path = self.remote_path.clone();
+        let local_path = self.local_path.clone();
+        let process_handler = self.process_handler.clone();
+        let event_handler = self.event_handler.clone();
+        let observers = self.observers.clone();
+        read_blocking(res, move |archive| {
+            let mut process = Process {
+                current: 0,
+                all,
+                rate_limit: None,
+            };
+            let mut written = 0;
+            let wanted = |path: &str| wanted.contains(path);
+            archive::extract(archive, &remote_path, &local_path, wanted, |entry| {
+                let bytes = entry.size.unwrap_or_default();
+                written += bytes;
+                process.done();
+                observers
+                    .iter()
+                    .for_each(|o| o.on_file_done(&entry, process));
+                event_handler(&DownloadEvent::FileCompleted {
+                    entry,
+                    bytes,
+                    process,
+                    would_download: true,
+                });
+                process_handler(process);
+            })?;
+            Ok((process.current, written))
+        })
+        .await
+    }
+
+    // the events of a download are sent as if the files were written, without fetching any
+    async fn preview(&self, tasks: Vec<Entry>, started: Instant) -> Result<DownloadReport> {
+        let mut process = Process {
+            current: 0,
+            all: tasks.len(),
+            rate_limit: None,
+        };
+        let (attempted, mut up_to_date, mut bytes) = (tasks.len(), 0, 0);
+        let written = self.written(&tasks)?;
+        for entry in tasks {
+            (self.event_handler)(&DownloadEvent::FileQueued {
+                entry: entry.clone(),
+            });
+            self.observers.iter().for_each(|o| o.on_file_start(&entry));
+            let dst = self.destination(&entry.path)?;
+            let would_download = !self.left_as_is(&entry, &dst).await?;
+            let size = entry.size.unwrap_or_default();
+            match would_download {
+                true => bytes += size,
+                false => up_to_date += 1,
+            }
+            process.done();
+            self.observers
+                .iter()
+                .for_each(|o| o.on_file_done(&entry, process));
+            (self.event_handler)(&DownloadEvent::FileCompleted {
+                entry,
+                bytes: size,
+                process,
+                would_download,
+            });
+            (self.process_handler)(process);
+        }
+        Ok(self.complete(DownloadReport {
+            branch: self.remote.branch.clone(),
+            attempted,
+            succeeded: attempted - up_to_date,
+            up_to_date,
+            failed: vec![],
+            deleted: self.prune(&written).await?,
+            bytes,
+            elapsed: started.elapsed(),
+        }))
+    }
+
+    fn start(&self, files: usize) {
+        self.observers.iter().for_each(|o| o.on_start(files));
+        (self.event_handler)(&DownloadEvent::Started { files });
+    }
+
+    fn complete(&self, mut report: DownloadReport) -> DownloadReport {
+        for nested in self.nested.lock().unwrap().drain(..) {
+            report.attempted += nested.attempted;
+            report.succeeded += nested.succeeded;
+            report.up_to_date += nested.up_to_date;
+            report.failed.extend(nested.failed);
+            report.deleted.extend(nested.deleted);
+            report.bytes += nested.bytes;
         }
+        self.observers.iter().for_each(|o| o.on_complete(&report));
+        (self.event_handler)(&DownloadEvent::Finished {
+            report: report.clone(),
+        });
+        report
+    }
+
+    /// Downloads in the background and yields its events, the download is aborted when
+    /// the stream is dropped
+    pub fn stream(&self) -> impl Stream<Item = DownloadEvent> + Send {
+        let mut downloader = self.clone();
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let handler = downloader.event_handler.clone();
+        let events = tx.clone();
+        downloader.event_handler = Arc::new(move |event| {
+            handler(event);
+            events.send(event.clone()).ok();
+        });
+        let download = tokio::spawn(async move {
+            if let Err(err) = downloader.download().await {
+                let error = Arc::new(err);
+                tx.send(DownloadEvent::Failed { error }).ok();
+            }
+        });
+        let state = (rx, AbortOnDrop(download));
+        unfold(state, |(mut rx, download)| async move {
+            let event = rx.recv().await?;
+            Some((event, (rx, download)))
+        })
+    }
+
+    /// Start a [`DownloaderBuilder`], which needs what to download before it is built, such
+    /// as `Downloader::builder().repo("<user>", "<repo>", "<directory>")`
+    pub fn builder() -> DownloaderBuilder<NoSource> {
+        DownloaderBuilder::default()
+    }
+
+    /// Shorthand for [`DownloaderBuilder::from_spec`] with the default settings
+    pub fn from_spec(spec: &str) -> Result<Self> {
+        Ok(DownloaderBuilder::from_spec(spec)?.build()?)
+    }
+
+    /// Shorthand for [`DownloaderBuilder::from_url`] with the default settings
+    pub fn from_url(url: &str) -> Result<Self> {
+        Ok(DownloaderBuilder::from_url(url)?.build()?)
+    }
+
+    // the blobs of the tree, or only those changed since the commit of a download of the
+    // changes, with the files removed since, when the forge tells them
+    async fn blobs(&self) -> Result<(Remote, Vec<Entry>, Option<Vec<String>>, Option<String>)> {
+        if let Some(since) = &self.since {
+            let mut remote = self.remote().await?;
+            remote.branch = self.provider.resolve(&self.client, &remote).await?;
+            if let Some(changes) = self.provider.changes(&self.client, &remote, since).await? {
+                let (mut blobs, mut removed) = (vec![], vec![]);
+                for change in changes {
+                    match change {
+                        Change::Changed { path, sha } => blobs.push(Entry {
+                            path,
+                            kind: EntryKind::File,
+                            size: None,
+                            sha,
+                            mode: None,
+                        }),
+                        Change::Removed { path } => removed.push(path),
+                    }
+                }
+                return Ok((remote, blobs, Some(removed), None));
+            }
+        }
+        let (remote, tree, commit) = self.listing().await?;
+        let blobs = tree
+            .into_iter()
+            .filter_map(Entry::from_node)
+            .filter(|entry| {
+                let module = self.submodules && entry.kind == EntryKind::Submodule;
+                entry.is_blob() || module && self.on_disk()
+            })
+            .collect();
+        Ok((remote, blobs, None, commit))
+    }
+
+    // each submodule is downloaded whole at its pinned commit into the directory of its
+    // gitlink, through the same provider and with the same settings, but none of the path
+    // rules, its events are handed on as files of the run, it is cut off along with the run
+    // and by the run's deadline
+    async fn download_submodules(&mut self, modules: &[Entry], started: Instant) -> Result<()> {
+        if modules.is_empty() {
+            return Ok(());
+        }
+        let content = tokio::select! {
+            content = self.content(".gitmodules") => content?,
+            err = self.interrupted(started) => return Err(err),
+        };
+        let urls = gitmodules(&String::from_utf8_lossy(&content));
+        for entry in modules {
+            let (Some(sha), Some(url)) = (&entry.sha, urls.get(&entry.path)) else {
+                return Err(anyhow!("{} has no commit or url to fetch", entry.path));
+            };
+            let (user, repo) = submodule_repo(url, &self.remote)
+                .ok_or_else(|| anyhow!("{} has a url {url} that names no repo", entry.path))?;
+            let dst = self.destination(&entry.path)?;
+            let mut module = self.clone();
+            module.remote = Remote {
+                user,
+                repo,
+                branch: sha.clone(),
+                path: String::new(),
+                token: None,
+            };
+            module.remote_path = String::new();
+            module.paths = vec![];
+            module.local_path = dst.clone();
+            module.filters = vec![];
+            module.rewrites = vec![];
+            module.map_destination = None;
+            module.flatten = false;
+            module.destinations = Arc::default();
+            module.etags = self.etags.as_ref().map(|_| Arc::new(ETags::load(&dst)));
+            module.lockfile = None;
+            module.locked = None;
+            module.commit = None;
+            module.since = None;
+            module.removed = None;
+            module.staging = None;
+            module.deadline_start = Some(started);
+            // the run started and completes around its submodules
+            let handler = self.event_handler.clone();
+            module.event_handler = Arc::new(move |event| {
+                if !matches!(
+                    event,
+                    DownloadEvent::Started { .. } | DownloadEvent::Finished { .. }
+                ) {
+                    handler(event);
+                }
+            });
+            module.observers = self
+                .observers
+                .iter()
+                .map(|o| Arc::new(observer::Nested(o.clone())) as Arc<dyn ProgressObserver>)
+                .collect();
+            let report = Box::pin(module.run()).await?;
+            self.nested.lock().unwrap().push(report);
+            self.modules.push(dst);
+        }
+        Ok(())
+    }
+
+    // the tree at the resolved ref, along with the remote it is listed at and the commit it
+    // was pinned to
+    async fn listing(&self) -> Result<(Remote, Vec<Node>, Option<String>)> {
+        let mut remote = self.remote().await?;
+        remote.branch = self.provider.resolve(&self.client, &remote).await?;
+        let (tree, commit) = match self.pinned_tree(&remote).await {
+            Ok(listed) => listed,
+            // repos still on the old default branch are retried with master, and vice versa
+            Err(err) => {
+                remote.branch = match remote.branch.as_str() {
+                    "main" => "master".into(),
+                    "master" => "main".into(),
+                    _ => return Err(err),
+                };
+                self.pinned_tree(&remote).await.map_err(|_| err)?
+            }
+        };
+        Ok((remote, tree, commit))
+    }
+
+    // a lockfile records the files of the commit the ref points to, so the ref is resolved
+    // to it once and the tree listed at it, as the ref could move on before the files are
+    // fetched
+    async fn pinned_tree(&self, remote: &Remote) -> Result<(Vec<Node>, Option<String>)> {
+        let commit = match self.lockfile.is_some() {
+            true => self.provider.commit(&self.client, remote).await?,
+            false => None,
+        };
+        let mut pinned = remote.clone();
+        if let Some(commit) = &commit {
+            pinned.branch.clone_from(commit);
+        }
+        Ok((self.provider.tree(&self.client, &pinned).await?, commit))
+    }
+
+    /// The files and directories below the remote path, listed without downloading, such as
+    /// to pick some or to add up their sizes first
+    pub async fn list(&self) -> Result<Vec<Entry>, Error> {
+        let (_, tree, _) = self.listing().await?;
+        Ok(tree
+            .into_iter()
+            .filter_map(Entry::from_node)
+            .filter(|entry| self.wanted(entry))
+            .collect())
+    }
+
+    // below the remote path, and a file the filters let through
+    fn wanted(&self, entry: &Entry) -> bool {
+        let path = Path::new(&entry.path);
+        let below = match self.paths.is_empty() {
+            true => path.starts_with(&self.remote_path),
+            false => self.paths.iter().any(|remote| path.starts_with(remote)),
+        };
+        let skipped = entry.kind == EntryKind::Symlink && self.symlinks == SymlinkPolicy::Skip;
+        below && !skipped && (!entry.is_blob() || self.filters.iter().all(|filter| filter(entry)))
+    }
+
+    // resolves once the download is cancelled or past its deadline, with the reason
+    async fn interrupted(&self, started: Instant) -> anyhow::Error {
+        let expired = async {
+            match self.deadline {
+                Some(deadline) => {
+                    tokio::time::sleep_until((started + deadline).into()).await;
+                    Error::DeadlineExceeded(deadline).into()
+                }
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = self.handle.token.cancelled() => Error::Cancelled.into(),
+            err = expired => err,
+        }
+    }
+
+    // the files a staged run wrote are moved to their destinations, those left as is by
+    // the update have nothing staged
+    async fn commit_staged(&self, staging: &Path, completed: &[Entry]) -> Result<()> {
+        let mut moves = vec![];
+        for entry in completed {
+            let dst = self.destination(&entry.path)?;
+            let staged = self.staged(&dst);
+            if fs::symlink_metadata(&staged).await.is_ok() {
+                moves.push((staged, dst));
+            }
+        }
+        let backup = self.overwrite == OverwritePolicy::Backup;
+        staging::commit(staging, &self.local_path, moves, backup)
+            .await
+            .map_err(|source| {
+                Error::WriteFailed {
+                    path: self.local_path.clone(),
+                    source,
+                }
+                .into()
+            })
+    }
+
+    // files cut off by a cancellation or the deadline are removed, or left to be resumed
+    async fn discard_in_flight(&self) {
+        let in_flight: Vec<_> = self.in_flight.lock().unwrap().drain().collect();
+        if !self.resume {
+            for dst in in_flight {
+                fs::remove_file(dst).await.ok();
+            }
+        }
+    }
+
+    /// Controls the download once it runs, such as to pause or cancel it
+    pub fn handle(&self) -> DownloadHandle {
+        self.handle.clone()
+    }
+
+    /// Downloads with the settings built, as often as needed
+    pub async fn download(&self) -> Result<DownloadReport, Error> {
+        self.clone().run().await.map_err(Error::from)
+    }
+
+    /// Compares the local files with the remote tree by their size and git blob sha,
+    /// without transferring any content, such as to audit a tree downloaded before
+    pub async fn verify(&self) -> Result<Verification, Error> {
+        Ok(self.audit("verify").await?.1)
+    }
+
+    /// What a [sync](Downloader::sync) would change, told apart as with
+    /// [`verify`](Downloader::verify), each updated text file with a unified diff of
+    /// `context` lines around the changes unless that is none, then the remote file is fetched
+    pub async fn diff(&self, context: Option<usize>) -> Result<Diff, Error> {
+        let (downloader, verification) = self.audit("diff").await?;
+        let mut updated = vec![];
+        for entry in verification.modified {
+            let path = downloader.destination(&entry.path)?;
+            let unified = match context {
+                Some(context) => downloader.unified(&entry, &path, context).await?,
+                None => None,
+            };
+            updated.push(FileDiff {
+                entry,
+                path,
+                unified,
+            });
+        }
+        Ok(Diff {
+            added: verification.missing,
+            updated,
+            removed: verification.extra,
+        })
+    }
+
+    // the local files against the remote tree, with the downloader the destinations are
+    // planned on
+    async fn audit(&self, field: &'static str) -> Result<(Downloader, Verification)> {
+        if !self.on_disk() {
+            return Err(Error::InvalidSetting {
+                field,
+                reason: "the files aren't written to the local path".into(),
+            }
+            .into());
+        }
+        let mut downloader = self.clone();
+        downloader.sync = true;
+        let (_, tree, _) = downloader.listing().await?;
+        let (modules, tasks): (Vec<_>, _) = tree
+            .into_iter()
+            .filter_map(Entry::from_node)
+            .filter(|entry| entry.is_blob() || entry.kind == EntryKind::Submodule)
+            .filter(|entry| downloader.wanted(entry))
+            .partition(|entry| entry.kind == EntryKind::Submodule);
+        if downloader.submodules {
+            for entry in modules {
+                let dst = downloader.destination(&entry.path)?;
+                downloader.modules.push(dst);
+            }
+        }
+        let tasks = downloader.plan_destinations(tasks)?;
+        let kept = downloader.written(&tasks)?;
+        let mut verification = Verification::default();
+        for entry in tasks {
+            let dst = downloader.destination(&entry.path)?;
+            let unknown = entry.size.is_none() && entry.sha.is_none();
+            match fs::symlink_metadata(&dst).await {
+                Err(_) => verification.missing.push(entry),
+                // without a size or a sha a file can't be told modified
+                Ok(_) if unknown || downloader.matches_remote(&entry, &dst).await? => {
+                    verification.intact += 1
+                }
+                Ok(_) => verification.modified.push(entry),
+            }
+        }
+        verification.extra = downloader.extraneous(&kept).await?;
+        Ok((downloader, verification))
+    }
+
+    // the changes from the local file to the remote one, none unless both are text
+    async fn unified(&self, entry: &Entry, path: &Path, context: usize) -> Result<Option<String>> {
+        let local = fs::read(path).await?;
+        let remote = self.content(&entry.path).await?;
+        let text = |bytes: &[u8]| {
+            let text = std::str::from_utf8(bytes).ok()?;
+            (!text.contains('\0')).then(|| text.to_string())
+        };
+        let (Some(local), Some(remote)) = (text(&local), text(&remote)) else {
+            return Ok(None);
+        };
+        let diff = similar::TextDiff::from_lines(&local, &remote);
+        let unified = diff
+            .unified_diff()
+            .context_radius(context)
+            .header(&path.to_string_lossy(), &entry.path)
+            .to_string();
+        Ok(Some(unified))
+    }
+
+    /// Brings a tree downloaded with a [lockfile](DownloaderBuilder::lockfile) up to date
+    /// with its ref, like a `git pull` of a single folder: the files whose blob sha changed
+    /// since the lockfile are fetched, those gone from the remote tree are removed unless
+    /// edited since, and the lockfile is rewritten, a missing one is written by a first
+    /// download
+    pub async fn update(&self) -> Result<DownloadReport, Error> {
+        let Some(path) = &self.lockfile else {
+            return Err(Error::InvalidSetting {
+                field: "lockfile",
+                reason: "an update needs one to compare with".into(),
+            });
+        };
+        let mut downloader = self.clone();
+        match Lockfile::load(path) {
+            Ok(locked) => {
+                let repo = format!("{}/{}", self.remote.user, self.remote.repo);
+                if locked.repo != repo || locked.path != self.remote_path {
+                    return Err(Error::InvalidSetting {
+                        field: "lockfile",
+                        reason: format!("it locks {} of {}", locked.path, locked.repo),
+                    });
+                }
+                downloader.locked = Some(Arc::new(locked));
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(Error::InvalidSetting {
+                    field: "lockfile",
+                    reason: err.to_string(),
+                })
+            }
+        }
+        downloader.run().await.map_err(Error::from)
+    }
+
+    /// Downloads only the files below the remote path changed since the commit `sha`, as the
+    /// forge's compare api tells them, instead of listing and hashing the whole tree, the
+    /// files removed since are removed, each once [confirmed](DownloaderBuilder::confirm_delete),
+    /// a forge that can't compare falls back to an [update](DownloaderBuilder::update)
+    pub async fn download_changes_since(&self, sha: &str) -> Result<DownloadReport, Error> {
+        let mut downloader = self.clone();
+        downloader.since = Some(sha.into());
+        downloader.run().await.map_err(Error::from)
+    }
+
+    /// Keeps the local path in sync with the remote path without git or webhooks, the ref
+    /// is polled every `interval` and [synced](Downloader::sync) once it moved, with the
+    /// events of a download, a failed sync is sent as [`DownloadEvent::Failed`] and tried
+    /// again at the next poll, until the download is cancelled through its
+    /// [handle](Downloader::handle)
+    pub async fn watch(&self, interval: Duration) -> Result<(), Error> {
+        let mut synced = None;
+        loop {
+            // a forge that doesn't tell the commit is synced at every poll
+            let head = self.head().await.ok().flatten();
+            if head.is_none() || head != synced {
+                match self.sync().await {
+                    Ok(_) => synced = head,
+                    Err(Error::Cancelled) => return Ok(()),
+                    Err(err) => {
+                        let error = Arc::new(err);
+                        (self.event_handler)(&DownloadEvent::Failed { error });
+                    }
+                }
+            }
+            tokio::select! {
+                _ = self.handle.token.cancelled() => return Ok(()),
+                _ = tokio::time::sleep(interval) => {}
+            }
+        }
+    }
+
+    // the commit the ref points to now, when the forge tells it
+    async fn head(&self) -> Result<Option<String>> {
+        let mut remote = self.remote().await?;
+        remote.branch = self.provider.resolve(&self.client, &remote).await?;
+        self.provider.commit(&self.client, &remote).await
+    }
+
+    /// Makes the local path mirror the remote path, the changed files are downloaded as with
+    /// [`update`](DownloaderBuilder::update) and the local files missing from the remote
+    /// tree are removed, each once [confirmed](DownloaderBuilder::confirm_delete), unless
+    /// a file failed
+    pub async fn sync(&self) -> Result<DownloadReport, Error> {
+        if !self.on_disk() {
+            return Err(Error::InvalidSetting {
+                field: "sync",
+                reason: "the files aren't written to the local path".into(),
+            });
+        }
+        let mut downloader = self.clone();
+        downloader.update = true;
+        downloader.sync = true;
+        downloader.run().await.map_err(Error::from)
+    }
+
+    // a run writing into an archive starts a new one, the files are written into it through
+    // the sink and it is finished once they are all in
+    fn open_bundle(&mut self) -> Result<Option<Arc<Bundle>>, Error> {
+        let Some((format, path)) = &self.bundle else {
+            return Ok(None);
+        };
+        let bundle = Bundle::create(*format, path).map_err(|source| Error::WriteFailed {
+            path: path.clone(),
+            source,
+        })?;
+        let bundle = Arc::new(bundle);
+        let writer = bundle.clone();
+        let remote_path = self.remote_path.clone();
+        self.sink = Some(Arc::new(move |entry, _| {
+            writer.sink(filter::relative(&entry.path, &remote_path), entry)
+        }));
+        Ok(Some(bundle))
+    }
+
+    // the destination after the path rewriting rules and flattening
+    fn rewritten(&self, path: &str) -> Result<PathBuf, Error> {
+        let dst = self.destination(path)?;
+        // a single file is already written to the local path itself
+        if dst == self.local_path {
+            return Ok(dst);
+        }
+        let path = filter::relative(path, &self.remote_path).to_string();
+        let path = self
+            .rewrites
+            .iter()
+            .fold(path, |path, rule| rule.apply(path));
+        let dst = self.local_path.join(path);
+        Ok(match (self.flatten, dst.file_name()) {
+            (true, Some(name)) => self.local_path.join(name),
+            _ => dst,
+        })
+    }
+
+    // decides where the files of the run go when it isn't their path below the local path,
+    // dropping those that aren't written at all
+    fn plan_destinations(&mut self, tasks: Vec<Entry>) -> Result<Vec<Entry>, Error> {
+        self.destinations = Arc::default();
+        if !self.flatten && self.rewrites.is_empty() && self.map_destination.is_none() {
+            return Ok(tasks);
+        }
+        let mut destinations = HashMap::new();
+        let mut taken = HashMap::<PathBuf, String>::new();
+        let mut kept = vec![];
+        for entry in tasks {
+            let mut dst = match &self.map_destination {
+                Some(map) => match map(Path::new(&entry.path)) {
+                    Some(path) => self.local_path.join(path),
+                    None => continue,
+                },
+                None => self.rewritten(&entry.path)?,
+            };
+            if let Some(first) = taken.get(&dst) {
+                match self.collision {
+                    CollisionPolicy::Fail => {
+                        return Err(Error::Collision {
+                            path: dst,
+                            first: first.clone(),
+                            second: entry.path,
+                        })
+                    }
+                    CollisionPolicy::KeepFirst => continue,
+                    CollisionPolicy::Rename => {
+                        dst = (2..)
+                            .map(|n| numbered(&dst, n))
+                            .find(|dst| !taken.contains_key(dst))
+                            .unwrap_or(dst);
+                    }
+                }
+            }
+            taken.insert(dst.clone(), entry.path.clone());
+            destinations.insert(entry.path.clone(), dst);
+            kept.push(entry);
+        }
+        self.destinations = Arc::new(destinations);
+        Ok(kept)
+    }
+
+    // with the error policy, a file in the way of one to be written fails the run before any
+    // transfer, those the update leaves as is are not in the way
+    async fn check_existing(&self, tasks: &[Entry]) -> Result<()> {
+        if self.overwrite != OverwritePolicy::Error || !self.stored() {
+            return Ok(());
+        }
+        let storage = self.storage();
+        for entry in tasks {
+            let dst = self.destination(&entry.path)?;
+            if storage.exists(&dst).await? && !self.left_as_is(entry, &dst).await? {
+                return Err(Error::FileExists(dst).into());
+            }
+        }
+        Ok(())
+    }
+
+    // what the lockfile of a run records, it is written once every file is
+    async fn lock(&self, remote: &Remote, tasks: &[Entry]) -> Result<Option<Lockfile>> {
+        // the changes alone would leave out the files unchanged
+        if self.lockfile.is_none() || self.removed.is_some() {
+            return Ok(None);
+        }
+        let files = tasks
+            .iter()
+            .filter_map(|entry| {
+                let path = filter::relative(&entry.path, &self.remote_path);
+                Some((path.to_string(), entry.sha.clone()?))
+            })
+            .collect();
+        Ok(Some(Lockfile {
+            repo: format!("{}/{}", remote.user, remote.repo),
+            reference: self.remote.branch.clone(),
+            // resolved when the tree was listed
+            commit: self.commit.clone(),
+            path: self.remote_path.clone(),
+            files,
+        }))
+    }
+
+    async fn write_lock(&self, lock: Option<Lockfile>) -> Result<()> {
+        let (Some(path), Some(lock)) = (&self.lockfile, lock) else {
+            return Ok(());
+        };
+        lock.save(path).await.map_err(|source| {
+            Error::WriteFailed {
+                path: path.clone(),
+                source,
+            }
+            .into()
+        })
+    }
+
+    // the paths a sync or an update keeps, those the files of the run are written to
+    fn written(&self, tasks: &[Entry]) -> Result<HashSet<PathBuf>> {
+        match self.sync || self.locked.is_some() {
+            true => tasks
+                .iter()
+                .map(|entry| self.destination(&entry.path))
+                .collect(),
+            false => Ok(HashSet::new()),
+        }
+    }
+
+    // the local files no file of the tree is written to, except those the filters leave
+    // out, as if they came from the same path below the remote path, and the backups of
+    // the backup policy
+    async fn extraneous(&self, kept: &HashSet<PathBuf>) -> Result<Vec<PathBuf>> {
+        // a single file has nothing beside it
+        let dir = fs::metadata(&self.local_path).await;
+        if !dir.is_ok_and(|meta| meta.is_dir()) {
+            return Ok(vec![]);
+        }
+        let mut skipped = vec![
+            staging_path(&self.local_path),
+            ETags::path(&self.local_path),
+        ];
+        // a submodule keeps its etags beside its directory
+        for module in &self.modules {
+            skipped.extend([module.clone(), ETags::path(module)]);
+        }
+        let mut extraneous = vec![];
+        for path in sync::walk(&self.local_path, &skipped).await? {
+            if kept.contains(&path) {
+                continue;
+            }
+            if self.overwrite == OverwritePolicy::Backup && path.extension() == Some("bak".as_ref())
+            {
+                continue;
+            }
+            let rest = path.strip_prefix(&self.local_path)?;
+            let rest = rest
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let remote = match self.remote_path.is_empty() {
+                true => rest,
+                false => format!("{}/{rest}", self.remote_path),
+            };
+            let size = fs::symlink_metadata(&path).await?.len();
+            let entry = Entry {
+                path: remote,
+                kind: EntryKind::File,
+                size: Some(size),
+                sha: None,
+                mode: None,
+            };
+            if self.filters.iter().all(|filter| filter(&entry)) {
+                extraneous.push(path);
+            }
+        }
+        Ok(extraneous)
+    }
+
+    // the files of the lockfile gone from the remote tree, those still holding the locked
+    // content, so a file the rewrites put elsewhere or edited since is kept
+    async fn unlocked(&self, locked: &Lockfile, kept: &HashSet<PathBuf>) -> Result<Vec<PathBuf>> {
+        let mut unlocked = vec![];
+        for (path, sha) in &locked.files {
+            let remote = match locked.path.is_empty() {
+                true => path.clone(),
+                false => format!("{}/{path}", locked.path),
+            };
+            let Ok(dst) = self.destination(&remote) else {
+                continue;
+            };
+            let entry = Entry {
+                path: remote,
+                kind: EntryKind::File,
+                size: None,
+                sha: Some(sha.clone()),
+                mode: None,
+            };
+            if !kept.contains(&dst) && self.matches_remote(&entry, &dst).await? {
+                unlocked.push(dst);
+            }
+        }
+        Ok(unlocked)
+    }
+
+    // the local files of those removed since the commit a download of changes starts from
+    async fn gone(&self, kept: &HashSet<PathBuf>) -> Vec<PathBuf> {
+        let mut gone = vec![];
+        for path in self.removed.iter().flatten() {
+            if !Path::new(path).starts_with(&self.remote_path) {
+                continue;
+            }
+            let Ok(dst) = self.destination(path) else {
+                continue;
+            };
+            if !kept.contains(&dst) && fs::symlink_metadata(&dst).await.is_ok() {
+                gone.push(dst);
+            }
+        }
+        gone
+    }
+
+    // a sync removes the extraneous local files, an update the files gone since the
+    // lockfile and a download of changes those removed since, a dry run only reports them
+    async fn prune(&self, kept: &HashSet<PathBuf>) -> Result<Vec<PathBuf>> {
+        let removed = match (self.sync, &self.locked) {
+            (true, _) => self.extraneous(kept).await?,
+            (false, Some(locked)) => self.unlocked(locked, kept).await?,
+            (false, None) => self.gone(kept).await,
+        };
+        let mut deleted = vec![];
+        for path in removed {
+            if !self.dry_run {
+                let confirm = self.confirm_delete.as_ref();
+                if confirm.is_some_and(|confirm| !confirm(&path)) {
+                    continue;
+                }
+                fs::remove_file(&path)
+                    .await
+                    .map_err(|source| Error::WriteFailed {
+                        path: path.clone(),
+                        source,
+                    })?;
+            }
+            (self.event_handler)(&DownloadEvent::FileDeleted { path: path.clone() });
+            deleted.push(path);
+        }
+        if !self.dry_run {
+            sync::remove_emptied(&self.local_path, &deleted).await;
+        }
+        Ok(deleted)
+    }
+
+    // the filtered tree is held against the limits before any transfer
+    fn check_limits(&self, tasks: &[Entry]) -> Result<(), Error> {
+        if let Some(limit) = self.max_files.filter(|&limit| tasks.len() > limit) {
+            return Err(Error::LimitExceeded {
+                what: "files",
+                actual: tasks.len() as u64,
+                limit: limit as u64,
+            });
+        }
+        let bytes = tasks.iter().filter_map(|entry| entry.size).sum();
+        match self.max_total_size.filter(|&limit| bytes > limit) {
+            Some(limit) => Err(Error::LimitExceeded {
+                what: "bytes",
+                actual: bytes,
+                limit,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    // a staged run leaves no staging directory behind, whether it is moved into place or
+    // rolled back
+    async fn run(mut self) -> Result<DownloadReport> {
+        self.modules = vec![];
+        self.nested = Arc::default();
+        let staging = self.staged.then(|| staging_path(&self.local_path));
+        let res = self.transfer().await;
+        if let Some(staging) = staging {
+            fs::remove_dir_all(staging).await.ok();
+        }
+        res
+    }
+
+    async fn transfer(mut self) -> Result<DownloadReport> {
+        let started = Instant::now();
+        let since = self.deadline_start.unwrap_or(started);
+        let (mut remote, blobs, removed, commit) = tokio::select! {
+            listed = self.blobs() => listed?,
+            err = self.interrupted(since) => return Err(err),
+        };
+        self.remote.branch.clone_from(&remote.branch);
+        // the files are fetched at the commit listed, the report still tells the ref
+        if let Some(commit) = &commit {
+            remote.branch.clone_from(commit);
+        }
+        self.commit = commit;
+        // the forge couldn't tell the changes, the local files are compared instead
+        self.update |= self.since.is_some() && removed.is_none();
+        self.removed = removed;
+
+        let (modules, blobs): (Vec<_>, Vec<_>) = blobs
+            .into_iter()
+            .partition(|entry| entry.kind == EntryKind::Submodule);
+        let all = blobs.len();
+        let tasks: Vec<_> = blobs
+            .into_iter()
+            .filter(|entry| self.wanted(entry))
+            .collect();
+        let modules: Vec<_> = modules
+            .into_iter()
+            .filter(|entry| self.wanted(entry))
+            .collect();
+        // each of several paths has to match something, not only one of them
+        if self.removed.is_none() {
+            let missing = self.paths.iter().find(|path| {
+                !tasks
+                    .iter()
+                    .chain(&modules)
+                    .any(|entry| Path::new(&entry.path).starts_with(path))
+            });
+            if let Some(path) = missing {
+                return Err(Error::PathNotFound(path.clone()).into());
+            }
+        }
+        let tasks = self.plan_destinations(tasks)?;
+        self.check_limits(&tasks)?;
+        self.check_existing(&tasks).await?;
+        self.download_submodules(&modules, since).await?;
+        // nothing changed since, or only removed files
+        if tasks.is_empty() && self.removed.is_some() {
+            self.start(0);
+            let deleted = self.prune(&HashSet::new()).await?;
+            return Ok(self.complete(DownloadReport {
+                branch: self.remote.branch.clone(),
+                attempted: 0,
+                succeeded: 0,
+                up_to_date: 0,
+                failed: vec![],
+                deleted,
+                bytes: 0,
+                elapsed: started.elapsed(),
+            }));
+        }
+        if self.dry_run {
+            if tasks.is_empty() && modules.is_empty() {
+                return Err(Error::PathNotFound(self.remote_path.clone()).into());
+            }
+            self.start(tasks.len());
+            return self.preview(tasks, started).await;
+        }
+        let bundle = self.open_bundle()?;
+        let lock = self.lock(&remote, &tasks).await?;
+        if let Some((_, entries)) = &mut self.manifest {
+            *entries = Arc::default();
+        }
+        // files written elsewhere than the disk have nothing to move into place
+        if self.staged && self.on_disk() {
+            let staging = staging_path(&self.local_path);
+            fs::remove_dir_all(&staging).await.ok();
+            self.staging = Some(staging);
+        }
+
+        // one request for the whole tree instead of one per file
+        let archive = match !tasks.is_empty() && self.use_archive(&tasks, all) {
+            true => self.provider.archive(&self.client, &remote),
+            false => None,
+        };
+        self.start(tasks.len());
+        if let Some(req) = archive {
+            let (succeeded, bytes) = tokio::select! {
+                written = self.download_archive(req, &tasks) => written?,
+                err = self.interrupted(since) => return Err(err),
+            };
+            self.write_lock(lock).await?;
+            return Ok(self.complete(DownloadReport {
+                branch: self.remote.branch.clone(),
+                attempted: tasks.len(),
+                succeeded,
+                up_to_date: 0,
+                failed: vec![],
+                deleted: vec![],
+                bytes,
+                elapsed: started.elapsed(),
+            }));
+        }
+
+        // bounded, so tasks wait for their update to be taken instead of it being dropped
+        let (tx, mut rx) = channel::<DownloadEvent>(self.max_concurrency);
+
+        let attempted = tasks.len();
+        if attempted == 0 && modules.is_empty() {
+            return Err(Error::PathNotFound(self.remote_path.clone()).into());
+        }
+        let me = Arc::new(self);
+
+        // dropping the download aborts the tasks still running
+        let kept = me.written(&tasks)?;
+        let mut set = Self::spawn_tasks(me.clone(), tasks, tx);
+
+        let (mut succeeded, mut up_to_date, mut written, mut failed) = (0, 0, 0, vec![]);
+        let mut completed = vec![];
+        loop {
+            let event = tokio::select! {
+                event = rx.recv() => event,
+                err = me.interrupted(since) => {
+                    set.abort_all();
+                    while set.join_next().await.is_some() {}
+                    me.discard_in_flight().await;
+                    return Err(err);
+                }
+            };
+            let Some(event) = event else {
+                break;
+            };
+            (me.event_handler)(&event);
+            match event {
+                DownloadEvent::FileCompleted {
+                    entry,
+                    bytes,
+                    process,
+                    ..
+                } => {
+                    succeeded += 1;
+                    written += bytes;
+                    (me.process_handler)(process);
+                    if me.staging.is_some() {
+                        completed.push(entry);
+                    }
+                }
+                DownloadEvent::FileUpToDate { process, .. } => {
+                    up_to_date += 1;
+                    (me.process_handler)(process);
+                }
+                DownloadEvent::FileFailed { entry, error } => {
+                    (me.error_handler)(&entry, &error);
+                    if me.error_policy == ErrorPolicy::FailFast {
+                        set.abort_all();
+                        while set.join_next().await.is_some() {}
+                        me.discard_in_flight().await;
+                        // the handlers may still hold the error, then it is passed on as a message
+                        return Err(Arc::try_unwrap(error).unwrap_or_else(|err| anyhow!("{err:#}")));
+                    }
+                    failed.push(FailedFile { entry, error });
+                }
+                _ => {}
+            }
+        }
+        // every task is gone, a panicked one is reported rather than the target
+        while let Some(joined) = set.join_next().await {
+            joined?;
+        }
+        if let Some(staging) = &me.staging {
+            // a failed file rolls the whole run back, nothing is written
+            if !failed.is_empty() {
+                return Ok(me.complete(DownloadReport {
+                    branch: me.remote.branch.clone(),
+                    attempted,
+                    succeeded: 0,
+                    up_to_date,
+                    failed,
+                    deleted: vec![],
+                    bytes: 0,
+                    elapsed: started.elapsed(),
+                }));
+            }
+            me.commit_staged(staging, &completed).await?;
+        }
+        if let Some(etags) = &me.etags {
+            etags.save().await?;
+        }
+        if let Some(bundle) = bundle {
+            tokio::task::spawn_blocking(move || bundle.finish()).await??;
+        }
+        if let Some((path, entries)) = &me.manifest {
+            let manifest = serde_json::to_vec_pretty(&*entries.lock().unwrap())?;
+            fs::write(path, manifest)
+                .await
+                .map_err(|source| Error::WriteFailed {
+                    path: path.clone(),
+                    source,
+                })?;
+        }
+        // a failed file could be one the sync would keep under another path, and the
+        // lockfile would record a file that isn't there
+        let deleted = match failed.is_empty() {
+            true => {
+                me.write_lock(lock).await?;
+                me.prune(&kept).await?
+            }
+            false => vec![],
+        };
+        Ok(me.complete(DownloadReport {
+            branch: me.remote.branch.clone(),
+            attempted,
+            succeeded,
+            up_to_date,
+            failed,
+            deleted,
+            bytes: written,
+            elapsed: started.elapsed(),
+        }))
+    }
+}
+
+/// Marks a [`DownloaderBuilder`] that is still missing what to download, such as the
+/// one [`Downloader::builder`] starts with
+#[derive(Default)]
+pub struct NoSource;
+
+/// Marks a [`DownloaderBuilder`] that knows what to download, only then it is built
+pub struct WithSource;
+
+/// The required source is given first, [`DownloaderBuilder::new`] and the like take it right
+/// away, the other settings are optional
+#[derive(Default)]
+pub struct DownloaderBuilder<S = WithSource> {
+    user: String,
+    repo: String,
+    // gists are addressed by their id alone
+    ownerless: bool,
+    branch: Option<String>,
+    remote_path: String,
+    paths: Vec<String>,
+    local_path: Option<PathBuf>,
+    auth: Option<Arc<dyn TokenSource>>,
+    anonymous: bool,
+    provider: Option<Arc<dyn Provider>>,
+    // a provider refused when it was set, answered by build
+    provider_error: Option<Error>,
+    raw_mirror: Option<String>,
+    raw_fallback: Option<String>,
+    client: Option<reqwest::Client>,
+    max_concurrency: Option<usize>,
+    retry: Retry,
+    file_timeout: Option<Duration>,
+    deadline: Option<Duration>,
+    resume: bool,
+    segments: Option<(u64, usize)>,
+    conditional: bool,
+    cache: Option<PathBuf>,
+    update: bool,
+    force: bool,
+    tarball: Tarball,
+    memory_budget: Option<u64>,
+    max_total_size: Option<u64>,
+    max_files: Option<usize>,
+    dry_run: bool,
+    overwrite: OverwritePolicy,
+    symlinks: SymlinkPolicy,
+    lfs_pointers: bool,
+    lockfile: Option<PathBuf>,
+    confirm_delete: Option<DeleteGuard>,
+    staged: bool,
+    submodules: bool,
+    sink: Option<Sink>,
+    bundle: Option<(bundle::Format, PathBuf)>,
+    storage: Option<Arc<dyn Storage>>,
+    manifest: Option<PathBuf>,
+    flatten: bool,
+    collision: CollisionPolicy,
+    rewrites: Vec<Rewrite>,
+    map_destination: Option<DestinationMap>,
+    pool_idle_timeout: Option<Duration>,
+    pool_max_idle_per_host: Option<usize>,
+    http2_prior_knowledge: bool,
+    tcp_keepalive: Option<Duration>,
+    process_handler: Option<ProcessHandler>,
+    event_handler: Option<EventHandler>,
+    error_handler: Option<ErrorHandler>,
+    error_policy: ErrorPolicy,
+    observers: Vec<Arc<dyn ProgressObserver>>,
+    filters: Vec<EntryFilter>,
+    include_globs: Vec<String>,
+    exclude_globs: Vec<String>,
+    ignore_files: Vec<PathBuf>,
+    #[cfg(feature = "regex")]
+    include_regexes: Vec<regex::Regex>,
+    #[cfg(feature = "regex")]
+    exclude_regexes: Vec<regex::Regex>,
+    cancellation_token: Option<CancellationToken>,
+    source: PhantomData<S>,
+}
+
+impl DownloaderBuilder<NoSource> {
+    /// Download the `remote` path of a repo, the whole repo for an empty path
+    pub fn repo(
+        mut self,
+        user: impl Into<String>,
+        repo: impl Into<String>,
+        remote: impl Into<String>,
+    ) -> DownloaderBuilder {
+        self.user = user.into();
+        self.repo = repo.into();
+        self.remote_path = remote.into();
+        self.with_source()
+    }
+
+    /// Download every file of the gist `id`, see [`provider::Gist`] for revisions and subsets
+    pub fn gist_files(mut self, id: impl Into<String>) -> DownloaderBuilder {
+        self.repo = id.into();
+        self.ownerless = true;
+        self.provider = Some(Arc::new(provider::Gist::default()));
+        self.with_source()
+    }
+
+    /// Download the assets of a GitHub release, such as `Release::latest().assets("*linux*")`
+    pub fn release_assets(
+        mut self,
+        user: impl Into<String>,
+        repo: impl Into<String>,
+        release: provider::Release,
+    ) -> DownloaderBuilder {
+        self.user = user.into();
+        self.repo = repo.into();
+        self.provider = Some(Arc::new(release));
+        self.with_source()
+    }
+
+    /// Download the wiki pages of a GitHub repo as markdown files into `<repo>.wiki`
+    pub fn wiki_pages(
+        mut self,
+        user: impl Into<String>,
+        repo: impl Into<String>,
+    ) -> DownloaderBuilder {
+        self.user = user.into();
+        self.repo = repo.into();
+        self.local_path = Some(format!("{}.wiki", self.repo).into());
+        self.provider = Some(Arc::new(provider::Wiki::default()));
+        self.with_source()
+    }
+
+    fn with_source(self) -> DownloaderBuilder {
+        DownloaderBuilder {
+            user: self.user,
+            repo: self.repo,
+            ownerless: self.ownerless,
+            branch: self.branch,
+            remote_path: self.remote_path,
+            paths: self.paths,
+            local_path: self.local_path,
+            auth: self.auth,
+            anonymous: self.anonymous,
+            provider: self.provider,
+            provider_error: self.provider_error,
+            raw_mirror: self.raw_mirror,
+            raw_fallback: self.raw_fallback,
+            client: self.client,
+            max_concurrency: self.max_concurrency,
+            retry: self.retry,
+            file_timeout: self.file_timeout,
+            deadline: self.deadline,
+            resume: self.resume,
+            segments: self.segments,
+            conditional: self.conditional,
+            cache: self.cache,
+            update: self.update,
+            force: self.force,
+            tarball: self.tarball,
+            memory_budget: self.memory_budget,
+            max_total_size: self.max_total_size,
+            max_files: self.max_files,
+            dry_run: self.dry_run,
+            overwrite: self.overwrite,
+            symlinks: self.symlinks,
+            lfs_pointers: self.lfs_pointers,
+            lockfile: self.lockfile,
+            confirm_delete: self.confirm_delete,
+            staged: self.staged,
+            submodules: self.submodules,
+            sink: self.sink,
+            bundle: self.bundle,
+            storage: self.storage,
+            manifest: self.manifest,
+            flatten: self.flatten,
+            collision: self.collision,
+            rewrites: self.rewrites,
+            map_destination: self.map_destination,
+            pool_idle_timeout: self.pool_idle_timeout,
+            pool_max_idle_per_host: self.pool_max_idle_per_host,
+            http2_prior_knowledge: self.http2_prior_knowledge,
+            tcp_keepalive: self.tcp_keepalive,
+            process_handler: self.process_handler,
+            event_handler: self.event_handler,
+            error_handler: self.error_handler,
+            error_policy: self.error_policy,
+            observers: self.observers,
+            filters: self.filters,
+            include_globs: self.include_globs,
+            exclude_globs: self.exclude_globs,
+            ignore_files: self.ignore_files,
+            #[cfg(feature = "regex")]
+            include_regexes: self.include_regexes,
+            #[cfg(feature = "regex")]
+            exclude_regexes: self.exclude_regexes,
+            cancellation_token: self.cancellation_token,
+            source: PhantomData,
+        }
+    }
+}
+
+impl DownloaderBuilder {
+    pub fn new(
+        user: impl Into<String>,
+        repo: impl Into<String>,
+        remote: impl Into<String>,
+    ) -> Self {
+        Downloader::builder().repo(user, repo, remote)
+    }
+
+    /// Parse `user/repo/path#ref`, optionally prefixed with `https://github.com/`
+    pub fn from_spec(spec: &str) -> Result<Self> {
+        let (spec, reference) = match spec.split_once('#') {
+            Some((spec, reference)) => (spec, Some(reference)),
+            None => (spec, None),
+        };
+        let spec = spec
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .trim_start_matches("github.com/")
+            .trim_matches('/');
+        let mut parts = spec.splitn(3, '/');
+        let (Some(user), Some(repo)) = (parts.next(), parts.next()) else {
+            return Err(anyhow!(
+                "Expected a spec like user/repo/path#ref, got {spec}"
+            ));
+        };
+        let repo = repo.trim_end_matches(".git");
+        if user.is_empty() || repo.is_empty() {
+            return Err(anyhow!(
+                "Expected a spec like user/repo/path#ref, got {spec}"
+            ));
+        }
+        let builder = Self::new(user, repo, parts.next().unwrap_or_default());
+        Ok(match reference {
+            Some(reference) if !reference.is_empty() => builder.branch(reference),
+            _ => builder,
+        })
+    }
+
+    /// Parse a url copied from the browser, such as
+    /// `https://github.com/user/repo/tree/<ref>/<dir>`, `.../blob/<ref>/<file>` or a
+    /// raw.githubusercontent.com one, a ref containing slashes takes only its first part
+    pub fn from_url(url: &str) -> Result<Self> {
+        let invalid =
+            || anyhow!("Expected a url like https://github.com/user/repo/tree/ref/path, got {url}");
+        let parsed = reqwest::Url::parse(url).map_err(|_| invalid())?;
+        let segments: Vec<_> = parsed
+            .path_segments()
+            .ok_or_else(invalid)?
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| percent_decode_str(segment).decode_utf8_lossy())
+            .collect();
+        let segments: Vec<_> = segments.iter().map(|segment| segment.as_ref()).collect();
+        let (user, repo, reference, path) = match (parsed.host_str(), segments.as_slice()) {
+            (Some("github.com" | "www.github.com"), [user, repo]) => (user, repo, None, &[][..]),
+            (
+                Some("github.com" | "www.github.com"),
+                [user, repo, "tree" | "blob", reference, path @ ..],
+            ) => (user, repo, Some(reference), path),
+            (Some("raw.githubusercontent.com"), [user, repo, reference, path @ ..]) => {
+                (user, repo, Some(reference), path)
+            }
+            _ => return Err(invalid()),
+        };
+        let builder = Self::new(*user, repo.trim_end_matches(".git"), path.join("/"));
+        Ok(match reference {
+            Some(reference) => builder.branch(*reference),
+            None => builder,
+        })
+    }
+
+    /// Shorthand for [`DownloaderBuilder::gist_files`] on [`Downloader::builder`]
+    pub fn gist(id: impl Into<String>) -> Self {
+        Downloader::builder().gist_files(id)
+    }
+
+    /// Shorthand for [`DownloaderBuilder::release_assets`] on [`Downloader::builder`]
+    pub fn release(
+        user: impl Into<String>,
+        repo: impl Into<String>,
+        release: provider::Release,
+    ) -> Self {
+        Downloader::builder().release_assets(user, repo, release)
+    }
+
+    /// Shorthand for [`DownloaderBuilder::wiki_pages`] on [`Downloader::builder`]
+    pub fn wiki(user: impl Into<String>, repo: impl Into<String>) -> Self {
+        Downloader::builder().wiki_pages(user, repo)
+    }
+
+    // the downloaded file or directory is named after the remote path, or the repo for its
+    // root or several paths
+    fn name(&self) -> PathBuf {
+        let remote = match self.paths.as_slice() {
+            [] => Path::new(&self.remote_path),
+            [path] => Path::new(path),
+            _ => return self.repo.clone().into(),
+        };
+        match remote.file_name() {
+            Some(name) => name.into(),
+            None => self.repo.clone().into(),
+        }
+    }
+
+    pub fn local_path(mut self, local: impl AsRef<Path>) -> Self {
+        self.local_path = Some(local.as_ref().join(self.name()));
+        self
+    }
+}
+
+impl<S> DownloaderBuilder<S> {
+    /// Download several remote paths in one go, with a single listing, progress and report,
+    /// such as `paths(["nvim", "kitty", "scripts/install.sh"])` instead of the remote path
+    /// of the source, the files keep their paths below the directory the paths share
+    pub fn paths<I>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.paths = paths.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn branch(mut self, branch: impl Into<String>) -> Self {
+        self.branch = Some(branch.into());
+        self
+    }
+
+    /// Download the tagged state instead of a branch
+    pub fn tag(self, tag: impl AsRef<str>) -> Self {
+        self.branch(format!("refs/tags/{}", tag.as_ref()))
+    }
+
+    /// Download the state of a full or abbreviated commit sha instead of a branch
+    pub fn commit(self, sha: impl Into<String>) -> Self {
+        self.branch(sha)
+    }
+
+    /// Download from the head of a pull request instead of a branch
+    pub fn pull_request(self, number: u64) -> Self {
+        self.branch(format!("pull/{number}/head"))
+    }
+
+    /// Personal access token sent with every request, falls back to `GITHUB_TOKEN`, `GH_TOKEN`
+    /// and the gh cli's login (or `GITLAB_TOKEN`, `GITEA_TOKEN`, `SRHT_TOKEN` and
+    /// `AZURE_DEVOPS_EXT_PAT` for the other providers)
+    pub fn token(mut self, token: impl Into<String>) -> Self {
+        self.auth = Some(Arc::new(token.into()));
+        self
+    }
+
+    /// Send requests without any token, even when one could be discovered
+    pub fn anonymous(mut self) -> Self {
+        self.anonymous = true;
+        self
+    }
+
+    /// Rotate between several tokens to spread the rate limit
+    pub fn tokens(mut self, tokens: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.auth = Some(Arc::new(auth::TokenPool::new(tokens)));
+        self
+    }
+
+    /// Take tokens from a [`TokenSource`], such as a GitHub App installation
+    pub fn token_source(mut self, source: impl TokenSource + 'static) -> Self {
+        self.auth = Some(Arc::new(source));
+        self
+    }
+
+    /// Share an http client, it should send a user agent as the github api requires one
+    pub fn client(mut self, client: reqwest::Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// How long idle connections are kept in the pool, 90 seconds by default, ignored with
+    /// [`DownloaderBuilder::client`] like the other connection settings
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// How many idle connections are kept per host
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Speak HTTP/2 from the first request instead of negotiating it, for servers known
+    /// to support it
+    pub fn http2_prior_knowledge(mut self) -> Self {
+        self.http2_prior_knowledge = true;
+        self
+    }
+
+    /// Send TCP keep-alive probes on idle connections at `interval`
+    pub fn tcp_keepalive(mut self, interval: Duration) -> Self {
+        self.tcp_keepalive = Some(interval);
+        self
+    }
+
+    /// Download through a custom [`Provider`]
+    pub fn provider(mut self, provider: impl Provider + 'static) -> Self {
+        self.provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Fetch file content from a mirror while still listing the tree through the provider,
+    /// such as `https://mirror.example.com/{user}/{repo}/{branch}/{path}`
+    pub fn raw_mirror(mut self, template: impl Into<String>) -> Self {
+        self.raw_mirror = Some(template.into());
+        self
+    }
+
+    /// Fetch file content from the jsDelivr cdn instead of the raw host
+    pub fn jsdelivr(self) -> Self {
+        self.raw_mirror(Mirror::JSDELIVR)
+    }
+
+    /// Fetch file content from a mirror template whenever the raw host fails
+    pub fn raw_fallback(mut self, template: impl Into<String>) -> Self {
+        self.raw_fallback = Some(template.into());
+        self
+    }
+
+    /// Fall back to the jsDelivr cdn whenever the raw host fails
+    pub fn jsdelivr_fallback(self) -> Self {
+        self.raw_fallback(Mirror::JSDELIVR)
+    }
+
+    /// Download from a GitHub Enterprise Server, such as
+    /// `https://github.mycorp.com/api/v3` and `https://github.mycorp.com/raw`
+    pub fn github_enterprise(mut self, api: impl Into<String>, raw: impl Into<String>) -> Self {
+        self.provider = Some(Arc::new(GitHub::new(&api.into(), &raw.into())));
+        self
+    }
+
+    /// List the github.com tree through the GraphQL api, which needs a token, enterprise
+    /// servers take `.provider(GitHub::new(api, raw).graphql())`
+    pub fn graphql(mut self) -> Self {
+        self.provider = Some(Arc::new(GitHub::default().graphql()));
+        self
+    }
+
+    /// Download from a GitLab instance such as `https://gitlab.com`, a host that isn't a
+    /// url fails the build
+    pub fn gitlab(mut self, host: impl Into<String>) -> Self {
+        match GitLab::new(&host.into()) {
+            Ok(gitlab) => self.provider = Some(Arc::new(gitlab)),
+            Err(err) => self.provider_error = Some(err),
+        }
+        self
+    }
+
+    /// Download from a Gitea compatible instance such as `https://codeberg.org`, a host that
+    /// isn't a url fails the build
+    pub fn gitea(mut self, host: impl Into<String>) -> Self {
+        match Gitea::new(&host.into()) {
+            Ok(gitea) => self.provider = Some(Arc::new(gitea)),
+            Err(err) => self.provider_error = Some(err),
+        }
+        self
+    }
+
+    /// Download from sourcehut, such as `https://git.sr.ht`
+    pub fn sourcehut(mut self, host: impl Into<String>) -> Self {
+        self.provider = Some(Arc::new(SourceHut::new(&host.into())));
+        self
+    }
+
+    /// Download from AWS CodeCommit in `region`, the user is ignored
+    #[cfg(feature = "codecommit")]
+    pub fn codecommit(mut self, region: impl Into<String>) -> Self {
+        self.provider = Some(Arc::new(provider::CodeCommit::new(&region.into())));
+        self
+    }
+
+    /// Download from Azure DevOps Repos, the user being the organization
+    pub fn azure_devops(self, project: impl Into<String>) -> Self {
+        self.azure_devops_server(AzureDevOps::HOST, project)
+    }
+
+    /// Download from an Azure DevOps Server collection such as
+    /// `https://tfs.mycorp.com/tfs`, a host that isn't a url fails the build
+    pub fn azure_devops_server(
+        mut self,
+        host: impl Into<String>,
+        project: impl Into<String>,
+    ) -> Self {
+        match AzureDevOps::new(&host.into(), &project.into()) {
+            Ok(azure) => self.provider = Some(Arc::new(azure)),
+            Err(err) => self.provider_error = Some(err),
+        }
+        self
+    }
+
+    /// Files downloaded at the same time, 16 by default
+    pub fn max_concurrency(mut self, n: usize) -> Self {
+        self.max_concurrency = Some(n.max(1));
+        self
+    }
+
+    /// Attempts per file on timeouts, connection resets and server errors, 3 by default
+    pub fn retries(mut self, attempts: usize) -> Self {
+        self.retry.attempts = attempts.max(1);
+        self
+    }
+
+    /// Delay before the first retry, doubled with jitter on every further one up to `max`
+    pub fn retry_backoff(mut self, base: Duration, max: Duration) -> Self {
+        self.retry.base = base;
+        self.retry.max = max;
+        self
+    }
+
+    /// Give up an attempt at a file after `timeout`, a stalled connection is then retried
+    /// like other transient failures, time spent paused counts too
+    pub fn file_timeout(mut self, timeout: Duration) -> Self {
+        self.file_timeout = Some(timeout);
+        self
+    }
+
+    /// Fail the whole download when it isn't done after `deadline`, the files cut off are
+    /// handled the same as on cancellation
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Resume the `.part` files an interrupted or failed run left with a range request,
+    /// instead of downloading them from zero
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Fetch files of at least `threshold` bytes as `segments` byte ranges in parallel
+    pub fn segmented(mut self, threshold: u64, segments: usize) -> Self {
+        self.segments = Some((threshold.max(1), segments.max(1)));
+        self
+    }
+
+    /// Remember the ETags of downloaded files next to the destination and only transfer
+    /// the files changed since on the next run
+    pub fn conditional(mut self, conditional: bool) -> Self {
+        self.conditional = conditional;
+        self
+    }
+
+    /// Keep downloaded blobs in `dir` keyed by their git sha, so the same content is
+    /// copied from it instead of fetched again, even across repos
+    pub fn cache_dir(mut self, dir: impl AsRef<Path>) -> Self {
+        self.cache = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Skip the local files whose git blob sha already matches the remote tree, or their size
+    /// when the listing has no sha, so only the changed files are fetched and the others are
+    /// reported with [`DownloadEvent::FileUpToDate`]
+    pub fn update(mut self, update: bool) -> Self {
+        self.update = update;
+        self
+    }
+
+    /// Fetch every file fresh, ignoring the local files, the ETags, the cache and the
+    /// `.part` files left to resume, such as when the local files are suspected to be
+    /// corrupted, the fresh files still refresh the ETags and the cache
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Fetch the whole tree as one tar.gz and extract the requested path from it, far
+    /// fewer requests for big folders
+    pub fn tarball(mut self, tarball: Tarball) -> Self {
+        self.tarball = tarball;
+        self
+    }
+
+    /// Cap the bytes of the files in flight at once, a large file waits until the ones
+    /// before it leave it room, a file larger than the budget is fetched alone
+    pub fn memory_budget(mut self, bytes: u64) -> Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// Fail before anything is fetched when the files to download add up to more than
+    /// `bytes`, by the sizes the listing reports
+    pub fn max_total_size(mut self, bytes: u64) -> Self {
+        self.max_total_size = Some(bytes);
+        self
+    }
+
+    /// Fail before anything is fetched when there are more than `files` files to download
+    pub fn max_files(mut self, files: usize) -> Self {
+        self.max_files = Some(files);
+        self
+    }
+
+    /// List and filter the tree and send the events of a download, with `would_download` set
+    /// on each file, without fetching or writing anything
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// What is done with the files already at the paths written to, by default they are
+    /// replaced
+    pub fn overwrite(mut self, policy: OverwritePolicy) -> Self {
+        self.overwrite = policy;
+        self
+    }
+
+    /// What is written for the symlinks of the tree, real links by default
+    pub fn symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlinks = policy;
+        self
+    }
+
+    /// Whether the files kept in git lfs are downloaded from its batch api in place of the
+    /// pointers committed for them, true by default, on GitHub, GitLab and Gitea
+    pub fn lfs(mut self, resolve: bool) -> Self {
+        self.lfs_pointers = !resolve;
+        self
+    }
+
+    /// Write a [`Lockfile`] to `path`, such as `downgit.lock`, once every file is
+    /// downloaded, recording the repo, the ref, the commit it resolved to and the blob sha
+    /// of every file
+    pub fn lockfile(mut self, path: impl Into<PathBuf>) -> Self {
+        self.lockfile = Some(path.into());
+        self
+    }
+
+    /// Asked before a [sync](Downloader::sync) removes each local file missing from the
+    /// remote tree, which is kept when it answers false
+    pub fn confirm_delete(
+        mut self,
+        confirm: impl Fn(&Path) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.confirm_delete = Some(Arc::new(confirm));
+        self
+    }
+
+    /// Download everything into a staging directory beside the local path and move it into
+    /// place only once every file succeeded, a failed or cancelled run leaves the local path
+    /// untouched, such as when it is a live config directory
+    pub fn staged(mut self, staged: bool) -> Self {
+        self.staged = staged;
+        self
+    }
+
+    /// Download the submodules below the remote path too, each at the commit it is pinned to
+    /// and from the repo `.gitmodules` names, through the same provider, instead of leaving
+    /// their directories empty
+    pub fn submodules(mut self, submodules: bool) -> Self {
+        self.submodules = submodules;
+        self
+    }
+
+    /// Write every file into the writer `sink` answers for its destination instead of a local
+    /// file, such as a socket, a compression pipeline or a buffer, a retried attempt asks for
+    /// a new one, resuming, segments and the disk-backed checks of earlier runs are left out
+    pub fn sink<W: AsyncWrite + Send + 'static>(
+        mut self,
+        sink: impl Fn(&Path) -> W + Send + Sync + 'static,
+    ) -> Self {
+        self.sink = Some(Arc::new(move |_, path| Box::pin(sink(path))));
+        self
+    }
+
+    /// Write the files to `storage` instead of the local filesystem, at the paths below the
+    /// local path they would have on disk, the same as a [`DownloaderBuilder::sink`]
+    pub fn storage(mut self, storage: impl Storage + 'static) -> Self {
+        self.storage = Some(Arc::new(storage));
+        self
+    }
+
+    /// Store the files as their git blobs under `objects/<sha[0..2]>/<sha>` in the local path
+    /// and write `manifest`, a json object mapping their paths below the remote path to the
+    /// shas, the blobs stored before are shared, such as by the repos synced into one store
+    pub fn content_addressed(mut self, manifest: impl AsRef<Path>) -> Self {
+        self.manifest = Some(manifest.as_ref().into());
+        self
+    }
+
+    /// Write the files below `prefix`, such as `examples/`, without it, on top of the rules
+    /// rewriting the paths below the local path added before
+    pub fn strip_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.rewrites.push(Rewrite::StripPrefix(prefix.into()));
+        self
+    }
+
+    /// Write the files below `prefix` in the local path
+    pub fn add_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.rewrites.push(Rewrite::AddPrefix(prefix.into()));
+        self
+    }
+
+    /// Write the file or directory `from` as `to`, such as `config/` as `.config/`
+    pub fn rename(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.rewrites.push(Rewrite::Rename {
+            from: from.into(),
+            to: to.into(),
+        });
+        self
+    }
+
+    /// Decide where each file is written, or whether at all, from its path in the repo,
+    /// relative paths land below the local path and absolute ones where they say, the path
+    /// rewriting rules and flattening are left to it then
+    pub fn map_destination(
+        mut self,
+        map: impl Fn(&Path) -> Option<PathBuf> + Send + Sync + 'static,
+    ) -> Self {
+        self.map_destination = Some(Arc::new(map));
+        self
+    }
+
+    /// Write every file straight into the local path, without the directories leading to it
+    /// below the remote path, files sharing a name are handled by [`DownloaderBuilder::collision`]
+    pub fn flatten(mut self, flatten: bool) -> Self {
+        self.flatten = flatten;
+        self
+    }
+
+    /// What a download does with files written to the same path, such as when it is flattened,
+    /// by default it fails
+    pub fn collision(mut self, policy: CollisionPolicy) -> Self {
+        self.collision = policy;
+        self
+    }
+
+    /// Write the files into a single zip archive at `path` instead of the local path, kept
+    /// below the remote path the way they would be on disk, each file is added as it arrives
+    pub fn zip(mut self, path: impl AsRef<Path>) -> Self {
+        self.bundle = Some((bundle::Format::Zip, path.as_ref().into()));
+        self
+    }
+
+    /// Write the files into a single tar.gz archive at `path` in one pass, the same way as
+    /// [`DownloaderBuilder::zip`]
+    pub fn tar_gz(mut self, path: impl AsRef<Path>) -> Self {
+        self.bundle = Some((bundle::Format::TarGz, path.as_ref().into()));
+        self
+    }
+
+    /// Write the files into a single uncompressed tar archive at `path`, the same way as
+    /// [`DownloaderBuilder::zip`]
+    pub fn tar(mut self, path: impl AsRef<Path>) -> Self {
+        self.bundle = Some((bundle::Format::Tar, path.as_ref().into()));
+        self
+    }
+
+    /// Called as every file finishes, it may capture state such as a progress bar
+    pub fn on_process(mut self, f: impl Fn(Process) + Send + Sync + 'static) -> Self {
+        self.process_handler = Some(Arc::new(f));
+        self
+    }
+
+    /// Called with every [`DownloadEvent`], from the listing to the final report
+    pub fn on_event(mut self, f: impl Fn(&DownloadEvent) + Send + Sync + 'static) -> Self {
+        self.event_handler = Some(Arc::new(f));
+        self
+    }
+
+    /// Called with the entry and the error of every failed file
+    pub fn on_error(mut self, f: impl Fn(&Entry, &anyhow::Error) + Send + Sync + 'static) -> Self {
+        self.error_handler = Some(Arc::new(f));
+        self
+    }
+
+    /// Whether a failed file stops the download, by default it does
+    pub fn error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.error_policy = policy;
+        self
+    }
+
+    /// Cancel the download along with `token`, like [`DownloadHandle::cancel`]
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation_token = Some(token);
+        self
+    }
+
+    /// Download only the files `filter` answers true for, on top of the filters added before,
+    /// they are skipped before anything is fetched
+    pub fn filter(mut self, filter: impl Fn(&Entry) -> bool + Send + Sync + 'static) -> Self {
+        self.filters.push(Arc::new(filter));
+        self
+    }
+
+    /// Download only the files matching `pattern`, or one of the patterns added before, such
+    /// as `**/*.lua`, matched against the path below the remote path
+    pub fn include_glob(mut self, pattern: impl Into<String>) -> Self {
+        self.include_globs.push(pattern.into());
+        self
+    }
+
+    /// Skip the files matching `pattern`, such as `**/node_modules/**`
+    pub fn exclude_glob(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude_globs.push(pattern.into());
+        self
+    }
+
+    /// Skip the files the listing reports larger than `bytes`, those of unknown size are kept
+    pub fn skip_files_larger_than(self, bytes: u64) -> Self {
+        self.filter(move |entry| entry.size.is_none_or(|size| size <= bytes))
+    }
+
+    /// Skip the files the rules of a gitignore-style file exclude, such as a versioned
+    /// `.downgitignore`, as if it sat in the remote path
+    pub fn ignore_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.ignore_files.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Download only the files whose path below the remote path matches `regex`, or one of
+    /// the regexes added before
+    #[cfg(feature = "regex")]
+    pub fn include_regex(mut self, regex: regex::Regex) -> Self {
+        self.include_regexes.push(regex);
+        self
+    }
+
+    /// Skip the files whose path below the remote path matches `regex`
+    #[cfg(feature = "regex")]
+    pub fn exclude_regex(mut self, regex: regex::Regex) -> Self {
+        self.exclude_regexes.push(regex);
+        self
+    }
+
+    /// Watch the download with `observer`, on top of the ones registered before
+    pub fn observer(mut self, observer: impl ProgressObserver + 'static) -> Self {
+        self.observers.push(Arc::new(observer));
+        self
+    }
+}
+
+impl DownloaderBuilder {
+    // the coordinates are checked before anything is requested, and the remote path is
+    // normalized, such as ./nvim/ into nvim
+    fn validate(&mut self) -> Result<(), Error> {
+        let invalid = |field, reason: &str| Error::InvalidSetting {
+            field,
+            reason: reason.into(),
+        };
+        if let Some(err) = self.provider_error.take() {
+            return Err(err);
+        }
+        if self.repo.is_empty() {
+            return Err(invalid("repo", "it is empty"));
+        }
+        if self.user.is_empty() && !self.ownerless {
+            return Err(invalid("user", "it is empty"));
+        }
+        let normalize = |path: &str| {
+            let mut parts = vec![];
+            for component in Path::new(path).components() {
+                match component {
+                    Component::Normal(name) => parts.push(name.to_string_lossy().into_owned()),
+                    Component::ParentDir => {
+                        return Err(invalid("remote path", "it climbs out of the repo"))
+                    }
+                    _ => {}
+                }
+            }
+            Ok(parts)
+        };
+        let mut remote_path = normalize(&self.remote_path)?;
+        if !self.paths.is_empty() {
+            let mut paths = vec![];
+            for path in &self.paths {
+                paths.push(normalize(path)?);
+            }
+            // the paths are listed once below the directory they share
+            remote_path = paths[0].clone();
+            for path in &paths {
+                let shared = remote_path.iter().zip(path).take_while(|(a, b)| a == b);
+                remote_path.truncate(shared.count());
+            }
+            paths.sort();
+            paths.dedup();
+            self.paths = paths.iter().map(|path| path.join("/")).collect();
+            // a single one is just the remote path
+            if self.paths.len() == 1 {
+                self.paths.clear();
+            }
+        }
+        self.remote_path = remote_path.join("/");
+        for rule in &mut self.rewrites {
+            *rule = rule
+                .normalized()
+                .ok_or_else(|| invalid("path rewrite", "it climbs out of the local path"))?;
+        }
+        let local = self.local_path.as_ref().map(|local| local.as_os_str());
+        match local {
+            Some(local) if local.is_empty() => Err(invalid("local path", "it is empty")),
+            Some(local) if local.as_encoded_bytes().contains(&0) => {
+                Err(invalid("local path", "it contains a nul byte"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn build(mut self) -> Result<Downloader, Error> {
+        self.validate()?;
+        let globs = filter::globs(&self.include_globs, &self.exclude_globs, &self.remote_path)
+            .map_err(|err| Error::InvalidSetting {
+                field: "glob",
+                reason: err.to_string(),
+            })?;
+        self.filters.extend(globs);
+        let ignored =
+            filter::ignore_files(&self.ignore_files, &self.remote_path).map_err(|reason| {
+                Error::InvalidSetting {
+                    field: "ignore file",
+                    reason,
+                }
+            })?;
+        self.filters.extend(ignored);
+        #[cfg(feature = "regex")]
+        self.filters.extend(filter::regexes(
+            std::mem::take(&mut self.include_regexes),
+            std::mem::take(&mut self.exclude_regexes),
+            &self.remote_path,
+        ));
+        let name = self.name();
+        // a sink of its own takes the place of the storage
+        let storage = self.storage.filter(|_| self.sink.is_none());
+        let sink = self.sink.or_else(|| {
+            let storage = storage.clone()?;
+            let sink: Sink = Arc::new(move |_, path| storage::writer(storage.clone(), path));
+            Some(sink)
+        });
+        let provider = self.provider.unwrap_or_else(|| Arc::new(GitHub::default()));
+        let fallback = self
+            .raw_fallback
+            .map(|template| Mirror::new(provider.clone(), &template));
+        let provider = match &self.raw_mirror {
+            Some(template) => Arc::new(Mirror::new(provider, template)),
+            None => provider,
+        };
+        let auth = match self.anonymous {
+            true => None,
+            false => self.auth.or_else(|| {
+                let token = provider.discover_token()?;
+                Some(Arc::new(token) as Arc<dyn TokenSource>)
+            }),
+        };
+        // one client for every request, so connections and tls sessions are reused
+        let client = match self.client {
+            Some(client) => client,
+            None => {
+                let mut builder = Downloader::default_client();
+                if let Some(interval) = self.tcp_keepalive {
+                    builder = builder.tcp_keepalive(interval);
+                }
+                if let Some(timeout) = self.pool_idle_timeout {
+                    builder = builder.pool_idle_timeout(timeout);
+                }
+                if let Some(max) = self.pool_max_idle_per_host {
+                    builder = builder.pool_max_idle_per_host(max);
+                }
+                if self.http2_prior_knowledge {
+                    builder = builder.http2_prior_knowledge();
+                }
+                builder.build()?
+            }
+        };
+        let local_path = self.local_path.unwrap_or(name);
+        Ok(Downloader {
+            client,
+            remote: Remote {
+                user: self.user,
+                repo: self.repo,
+                branch: self.branch.unwrap_or("main".into()),
+                path: self.remote_path.clone(),
+                token: None,
+            },
+            provider,
+            fallback,
+            auth,
+            remote_path: self.remote_path,
+            paths: self.paths,
+            etags: self.conditional.then(|| Arc::new(ETags::load(&local_path))),
+            cache: self.cache,
+            update: self.update,
+            force: self.force,
+            tarball: self.tarball,
+            memory_budget: self.memory_budget.map(|bytes| {
+                let max = (u32::MAX as u64).min(Semaphore::MAX_PERMITS as u64);
+                let kib = bytes.div_ceil(1024).clamp(1, max) as u32;
+                (Arc::new(Semaphore::new(kib as usize)), kib)
+            }),
+            max_total_size: self.max_total_size,
+            max_files: self.max_files,
+            dry_run: self.dry_run,
+            sink,
+            storage,
+            bundle: self.bundle,
+            manifest: self.manifest.map(|path| (path, Arc::default())),
+            flatten: self.flatten,
+            collision: self.collision,
+            rewrites: self.rewrites,
+            map_destination: self.map_destination,
+            destinations: Arc::default(),
+            overwrite: self.overwrite,
+            symlinks: self.symlinks,
+            lfs_pointers: self.lfs_pointers,
+            lockfile: self.lockfile,
+            locked: None,
+            since: None,
+            removed: None,
+            sync: false,
+            confirm_delete: self.confirm_delete,
+            staged: self.staged,
+            staging: None,
+            submodules: self.submodules,
+            modules: vec![],
+            nested: Arc::default(),
+            deadline_start: None,
+            local_path,
+            max_concurrency: self.max_concurrency.unwrap_or(Downloader::MAX_CONCURRENCY),
+            permits: None,
+            retry: self.retry,
+            file_timeout: self.file_timeout,
+            deadline: self.deadline,
+            resume: self.resume,
+            segments: self.segments,
+            rate_limit: Arc::default(),
+            quotas: Arc::default(),
+            process_handler: self.process_handler.unwrap_or_else(|| Arc::new(|_| {})),
+            event_handler: self.event_handler.unwrap_or_else(|| Arc::new(|_| {})),
+            error_handler: self.error_handler.unwrap_or_else(|| Arc::new(|_, _| {})),
+            error_policy: self.error_policy,
+            observers: self.observers,
+            filters: self.filters,
+            handle: DownloadHandle::new(self.cancellation_token.unwrap_or_default()),
+            in_flight: Arc::default(),
+            commit: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::*;
+    use futures_util::{stream::BoxStream, TryStreamExt};
+
+    use crate::{
+        storage::async_trait,
+        stub::{self, Response, Stub, StubProvider, TempDir},
+    };
+
+    // downloads the files the stub serves into the dotfiles directory of `dir`
+    fn stubbed(stub: &Stub, files: &[(&str, &[u8])], dir: &TempDir) -> DownloaderBuilder {
+        DownloaderBuilder::new("levinion", "dotfiles", "")
+            .provider(StubProvider::new(stub, files))
+            .anonymous()
+            .local_path(dir.path())
+    }
+
+    fn now_secs() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[tokio::test]
+    async fn fails_a_file_backed_off_over_and_over() {
+        let stub = Stub::start(|_| Response::new(429).header("retry-after", 0)).await;
+        let dir = TempDir::new();
+        let downloader = stubbed(&stub, &[("init.lua", b"")], &dir).build().unwrap();
+        let err = downloader.download().await.unwrap_err();
+        assert!(
+            matches!(err, Error::RateLimited { quota: None, .. }),
+            "{err:?}"
+        );
+        let requests = stub.requests();
+        assert_eq!(requests.len(), rate_limit::ATTEMPTS);
+        assert!(requests.iter().all(|req| req.path == "/init.lua"));
+    }
+
+    #[tokio::test]
+    async fn fails_a_file_whose_quota_stays_used_up() {
+        let stub = Stub::start(|_| {
+            Response::new(403)
+                .header("x-ratelimit-remaining", 0)
+                .header("x-ratelimit-reset", now_secs() + 1)
+        })
+        .await;
+        let dir = TempDir::new();
+        let downloader = stubbed(&stub, &[("init.lua", b"")], &dir).build().unwrap();
+        let err = downloader.download().await.unwrap_err();
+        let Error::RateLimited {
+            quota: Some(quota), ..
+        } = err
+        else {
+            panic!("{err:?}");
+        };
+        assert_eq!(quota.remaining, 0);
+        assert_eq!(stub.requests().len(), rate_limit::ATTEMPTS);
+    }
+
+    // keeps the files in memory, to tell the downloader asks it rather than the disk
+    #[derive(Clone, Default)]
+    struct MemoryStorage(Arc<std::sync::Mutex<HashMap<PathBuf, Vec<u8>>>>);
+
+    #[async_trait]
+    impl Storage for MemoryStorage {
+        async fn put(
+            &self,
+            path: &Path,
+            content: BoxStream<'static, std::io::Result<bytes::Bytes>>,
+        ) -> Result<()> {
+            let chunks: Vec<_> = content.try_collect().await?;
+            let mut files = self.0.lock().unwrap();
+            files.insert(path.to_path_buf(), chunks.concat());
+            Ok(())
+        }
+
+        async fn metadata(&self, path: &Path) -> Result<Option<storage::Metadata>> {
+            let files = self.0.lock().unwrap();
+            Ok(files.get(path).map(|content| storage::Metadata {
+                size: content.len() as u64,
+            }))
+        }
+    }
+
+    fn serve(
+        files: &'static [(&'static str, &'static [u8])],
+    ) -> impl Fn(&stub::Request) -> Response {
+        move |req| match files
+            .iter()
+            .find(|(path, _)| req.path == format!("/{path}"))
+        {
+            Some((_, content)) => Response::ok(*content),
+            None => Response::new(404),
+        }
+    }
+
+    #[tokio::test]
+    async fn cuts_off_a_submodule_at_the_run_deadline() {
+        const FILES: &[(&str, &[u8])] = &[
+            (
+                ".gitmodules",
+                b"[submodule \"plugin\"]\n\tpath = plugin\n\turl = https://github.com/levinion/plugin\n",
+            ),
+            ("init.lua", b"init"),
+        ];
+        let stub = Stub::start(|req| match req.path.as_str() {
+            "/.gitmodules" => Response::ok(FILES[0].1).after(Duration::from_millis(800)),
+            _ => Response::ok(FILES[1].1).stalled(),
+        })
+        .await;
+        let dir = TempDir::new();
+        let provider = StubProvider::new(&stub, FILES).submodule("plugin", "abc123");
+        let downloader = stubbed(&stub, FILES, &dir)
+            .provider(provider)
+            .submodules(true)
+            .deadline(Duration::from_secs(1))
+            .build()
+            .unwrap();
+        let started = Instant::now();
+        let err = downloader.download().await.unwrap_err();
+        // the submodule, started 800ms in, doesn't get a deadline of its own
+        assert!(started.elapsed() < Duration::from_millis(1500));
+        assert!(matches!(err, Error::DeadlineExceeded(_)), "{err:?}");
+        assert!(!dir.path().join("dotfiles/plugin/init.lua").exists());
+    }
+
+    #[tokio::test]
+    async fn writes_to_the_local_storage_by_default() {
+        const FILES: &[(&str, &[u8])] = &[("lua/init.lua", b"vim.o.number = true")];
+        let stub = Stub::start(serve(FILES)).await;
+        let dir = TempDir::new();
+        let downloader = stubbed(&stub, FILES, &dir).build().unwrap();
+        downloader.download().await.unwrap();
+        let dst = dir.path().join("dotfiles/lua/init.lua");
+        let meta = LocalStorage.metadata(&dst).await.unwrap();
+        assert_eq!(meta.map(|meta| meta.size), Some(FILES[0].1.len() as u64));
+    }
+
+    #[tokio::test]
+    async fn asks_the_storage_what_it_holds() {
+        const FILES: &[(&str, &[u8])] = &[("kept.lua", b"kept"), ("new.lua", b"new")];
+        let stub = Stub::start(serve(FILES)).await;
+        let dir = TempDir::new();
+        let storage = MemoryStorage::default();
+        let kept = dir.path().join("dotfiles/kept.lua");
+        storage
+            .0
+            .lock()
+            .unwrap()
+            .insert(kept.clone(), b"old!".to_vec());
+
+        let downloader = stubbed(&stub, FILES, &dir)
+            .storage(storage.clone())
+            .overwrite(OverwritePolicy::Error)
+            .build()
+            .unwrap();
+        let err = downloader.download().await.unwrap_err();
+        assert!(
+            matches!(err, Error::FileExists(ref path) if *path == kept),
+            "{err:?}"
+        );
+
+        // a file of the listed size is taken as up to date
+        let downloader = stubbed(&stub, FILES, &dir)
+            .storage(storage.clone())
+            .update(true)
+            .build()
+            .unwrap();
+        downloader.download().await.unwrap();
+        let requests = stub.requests();
+        assert!(
+            requests.iter().all(|req| req.path == "/new.lua"),
+            "{requests:?}"
+        );
+        let files = storage.0.lock().unwrap();
+        assert_eq!(files[&kept], b"old!");
+        assert_eq!(files[&dir.path().join("dotfiles/new.lua")], b"new");
+        assert!(!dir.path().join("dotfiles").exists());
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_the_locked_commit() {
+        const SHA: &str = "0123456789abcdef0123456789abcdef01234567";
+        let stub = Stub::start(|req| match req.path.as_str() {
+            path if path == format!("/mirror/{SHA}/init.lua") => Response::ok("mirrored"),
+            _ => Response::new(404),
+        })
+        .await;
+        let dir = TempDir::new();
+        let files: &[(&str, &[u8])] = &[("init.lua", b"mirrored")];
+        let downloader = DownloaderBuilder::new("levinion", "dotfiles", "")
+            .provider(StubProvider::new(&stub, files).commit(SHA))
+            .anonymous()
+            .local_path(dir.path())
+            .lockfile(dir.path().join("gitload.lock"))
+            .raw_fallback(format!("{}/mirror/{{branch}}/{{path}}", stub.url))
+            .build()
+            .unwrap();
+        downloader.download().await.unwrap();
+        let content = std::fs::read(dir.path().join("dotfiles/init.lua")).unwrap();
+        assert_eq!(content, b"mirrored");
+    }
+
+    #[tokio::test]
+    async fn fails_fast_without_leaving_parts() {
+        let stub = Stub::start(|req| match req.path.as_str() {
+            "/big.bin" => Response::ok(vec![0; 4096]).stalled(),
+            _ => Response::new(404).after(Duration::from_millis(200)),
+        })
+        .await;
+        let dir = TempDir::new();
+        let files: &[(&str, &[u8])] = &[("big.bin", &[0; 8192]), ("missing.lua", b"")];
+        let downloader = stubbed(&stub, files, &dir).build().unwrap();
+        downloader.download().await.unwrap_err();
+        let part = part_path(&dir.path().join("dotfiles/big.bin"));
+        assert!(!part.exists(), "{part:?} was left behind");
+    }
+
+    #[tokio::test]
+    async fn applies_the_overwrite_policy() {
+        const FILES: &[(&str, &[u8])] = &[("init.lua", b"new"), ("other.lua", b"other")];
+        let stub = Stub::start(serve(FILES)).await;
+        let dir = TempDir::new();
+        let dst = dir.path().join("dotfiles/init.lua");
+        let download = |policy| {
+            std::fs::create_dir_all(dst.parent().unwrap()).unwrap();
+            std::fs::write(&dst, "old").unwrap();
+            std::fs::remove_file(dir.path().join("dotfiles/other.lua")).ok();
+            let downloader = stubbed(&stub, FILES, &dir).overwrite(policy).build();
+            async move { downloader.unwrap().download().await }
+        };
+
+        let report = download(OverwritePolicy::Skip).await.unwrap();
+        assert_eq!((report.succeeded, report.up_to_date), (1, 1));
+        assert_eq!(std::fs::read(&dst).unwrap(), b"old");
+
+        let before = stub.requests().len();
+        let err = download(OverwritePolicy::Error).await.unwrap_err();
+        assert!(
+            matches!(err, Error::FileExists(ref path) if *path == dst),
+            "{err:?}"
+        );
+        // nothing is fetched once a file is in the way
+        assert_eq!(stub.requests().len(), before);
+
+        download(OverwritePolicy::Backup).await.unwrap();
+        assert_eq!(std::fs::read(&dst).unwrap(), b"new");
+        assert_eq!(std::fs::read(backup_path(&dst)).unwrap(), b"old");
+
+        download(OverwritePolicy::Overwrite).await.unwrap();
+        assert_eq!(std::fs::read(&dst).unwrap(), b"new");
+    }
+
+    #[tokio::test]
+    async fn rolls_a_staged_download_back() {
+        const FILES: &[(&str, &[u8])] = &[("init.lua", b"new"), ("missing.lua", b"gone")];
+        let stub = Stub::start(serve(&FILES[..1])).await;
+        let dir = TempDir::new();
+        let local = dir.path().join("dotfiles");
+        std::fs::create_dir_all(&local).unwrap();
+        std::fs::write(local.join("init.lua"), "old").unwrap();
+        let downloader = stubbed(&stub, FILES, &dir)
+            .staged(true)
+            .error_policy(ErrorPolicy::ContinueAndReport)
+            .build()
+            .unwrap();
+        let report = downloader.download().await.unwrap();
+        assert_eq!((report.succeeded, report.failed.len()), (0, 1));
+        assert_eq!(std::fs::read(local.join("init.lua")).unwrap(), b"old");
+        assert!(!staging_path(&local).exists());
+
+        // once every file arrives the whole tree is moved into place
+        let downloader = stubbed(&stub, &FILES[..1], &dir).staged(true).build();
+        downloader.unwrap().download().await.unwrap();
+        assert_eq!(std::fs::read(local.join("init.lua")).unwrap(), b"new");
+        assert!(!staging_path(&local).exists());
+    }
+
+    #[tokio::test]
+    async fn syncs_away_the_files_missing_remotely() {
+        const FILES: &[(&str, &[u8])] = &[("lua/init.lua", b"init")];
+        let stub = Stub::start(serve(FILES)).await;
+        let dir = TempDir::new();
+        let local = dir.path().join("dotfiles");
+        std::fs::create_dir_all(local.join("lua/old")).unwrap();
+        std::fs::write(local.join("lua/old/plugin.lua"), "stale").unwrap();
+        std::fs::write(local.join("stale.lua"), "stale").unwrap();
+        let downloader = stubbed(&stub, FILES, &dir).build().unwrap();
+        let mut report = downloader.sync().await.unwrap();
+        report.deleted.sort();
+        let deleted = [local.join("lua/old/plugin.lua"), local.join("stale.lua")];
+        assert_eq!(report.deleted, deleted);
+        assert!(deleted.iter().all(|path| !path.exists()));
+        assert_eq!(std::fs::read(local.join("lua/init.lua")).unwrap(), b"init");
+    }
+
+    #[tokio::test]
+    async fn resumes_a_cut_off_file() {
+        const CONTENT: &[u8] = b"local function setup() end";
+        let stub = Stub::start(|req| match req.header("range") {
+            Some("bytes=6-") => Response::new(206)
+                .header(
+                    "content-range",
+                    format!("bytes 6-{}/{}", CONTENT.len() - 1, CONTENT.len()),
+                )
+                .body(&CONTENT[6..]),
+            _ => Response::ok(CONTENT),
+        })
+        .await;
+        let dir = TempDir::new();
+        let dst = dir.path().join("dotfiles/init.lua");
+        std::fs::create_dir_all(dst.parent().unwrap()).unwrap();
+        std::fs::write(part_path(&dst), &CONTENT[..6]).unwrap();
+        let files: &[(&str, &[u8])] = &[("init.lua", CONTENT)];
+        let downloader = stubbed(&stub, files, &dir).resume(true).build().unwrap();
+        let report = downloader.download().await.unwrap();
+        assert_eq!(report.bytes, CONTENT.len() as u64);
+        assert_eq!(std::fs::read(&dst).unwrap(), CONTENT);
+        assert!(!part_path(&dst).exists());
+        let requests = stub.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].header("range"), Some("bytes=6-"));
+
+        // a part that doesn't fit the listed sha is fetched again whole
+        std::fs::remove_file(&dst).unwrap();
+        std::fs::write(part_path(&dst), "garbag").unwrap();
+        let downloader = stubbed(&stub, files, &dir).resume(true).build().unwrap();
+        downloader.download().await.unwrap();
+        assert_eq!(std::fs::read(&dst).unwrap(), CONTENT);
+        assert_eq!(stub.requests()[2].header("range"), None);
+    }
+
+    #[tokio::test]
+    async fn leaves_a_file_answered_not_modified() {
+        let stub = Stub::start(|req| match req.header("if-none-match") {
+            Some("\"v1\"") => Response::new(304),
+            _ => Response::ok("init").header("etag", "\"v1\""),
+        })
+        .await;
+        let dir = TempDir::new();
+        let files: &[(&str, &[u8])] = &[("init.lua", b"init")];
+        let download = || async {
+            let downloader = stubbed(&stub, files, &dir).conditional(true).build();
+            downloader.unwrap().download().await.unwrap()
+        };
+        let report = download().await;
+        assert_eq!((report.succeeded, report.up_to_date), (1, 0));
+
+        let report = download().await;
+        assert_eq!((report.succeeded, report.up_to_date), (0, 1));
+        assert_eq!(report.bytes, 0);
+        assert_eq!(stub.requests()[1].header("if-none-match"), Some("\"v1\""));
+        let dst = dir.path().join("dotfiles/init.lua");
+        assert_eq!(std::fs::read(dst).unwrap(), b"init");
+    }
+
+    #[tokio::test]
+    async fn dry_runs_without_fetching_or_writing() {
+        const FILES: &[(&str, &[u8])] = &[("kept.lua", b"kept"), ("new.lua", b"new")];
+        let stub = Stub::start(serve(FILES)).await;
+        let dir = TempDir::new();
+        let local = dir.path().join("dotfiles");
+        std::fs::create_dir_all(&local).unwrap();
+        std::fs::write(local.join("kept.lua"), "kept").unwrap();
+        let planned = Arc::new(std::sync::Mutex::new(vec![]));
+        let seen = planned.clone();
+        let downloader = stubbed(&stub, FILES, &dir)
+            .dry_run(true)
+            .update(true)
+            .on_event(move |event| {
+                if let DownloadEvent::FileCompleted {
+                    entry,
+                    would_download,
+                    ..
+                } = event
+                {
+                    seen.lock()
+                        .unwrap()
+                        .push((entry.path.clone(), *would_download));
+                }
+            })
+            .build()
+            .unwrap();
+        let report = downloader.download().await.unwrap();
+        assert_eq!((report.succeeded, report.up_to_date), (1, 1));
+        assert_eq!(report.bytes, 3);
+        let planned = planned.lock().unwrap();
+        let expected = [
+            ("kept.lua".to_string(), false),
+            ("new.lua".to_string(), true),
+        ];
+        assert_eq!(*planned, expected);
+        assert!(stub.requests().is_empty());
+        assert!(!local.join("new.lua").exists());
+    }
+
+    fn coordinates(builder: &DownloaderBuilder) -> (&str, &str, &str, Option<&str>) {
+        let branch = builder.branch.as_deref();
+        (&builder.user, &builder.repo, &builder.remote_path, branch)
+    }
+
+    #[test]
+    fn parses_specs() {
+        let cases = [
+            ("levinion/dotfiles", ("levinion", "dotfiles", "", None)),
+            (
+                "levinion/dotfiles/nvim",
+                ("levinion", "dotfiles", "nvim", None),
+            ),
+            (
+                "levinion/dotfiles/config/nvim#dev",
+                ("levinion", "dotfiles", "config/nvim", Some("dev")),
+            ),
+            (
+                "https://github.com/levinion/dotfiles.git/nvim/",
+                ("levinion", "dotfiles", "nvim", None),
+            ),
+            // an empty ref keeps the default branch
+            ("levinion/dotfiles#", ("levinion", "dotfiles", "", None)),
+        ];
+        for (spec, expected) in cases {
+            let builder = DownloaderBuilder::from_spec(spec).unwrap();
+            assert_eq!(coordinates(&builder), expected, "{spec}");
+        }
+    }
+
+    #[test]
+    fn refuses_invalid_specs() {
+        for spec in [
+            "",
+            "levinion",
+            "/dotfiles",
+            "levinion/",
+            "https://github.com/levinion",
+        ] {
+            assert!(DownloaderBuilder::from_spec(spec).is_err(), "{spec}");
+        }
+    }
+
+    #[test]
+    fn parses_urls() {
+        let cases = [
+            (
+                "https://github.com/levinion/dotfiles",
+                ("levinion", "dotfiles", "", None),
+            ),
+            (
+                "https://github.com/levinion/dotfiles/tree/main/config/nvim",
+                ("levinion", "dotfiles", "config/nvim", Some("main")),
+            ),
+            (
+                "https://www.github.com/levinion/dotfiles/blob/v1.0/init%20file.lua",
+                ("levinion", "dotfiles", "init file.lua", Some("v1.0")),
+            ),
+            (
+                "https://raw.githubusercontent.com/levinion/dotfiles/main/nvim/init.lua",
+                ("levinion", "dotfiles", "nvim/init.lua", Some("main")),
+            ),
+            (
+                "https://github.com/levinion/dotfiles.git",
+                ("levinion", "dotfiles", "", None),
+            ),
+        ];
+        for (url, expected) in cases {
+            let builder = DownloaderBuilder::from_url(url).unwrap();
+            assert_eq!(coordinates(&builder), expected, "{url}");
+        }
+    }
+
+    #[test]
+    fn refuses_invalid_urls() {
+        for url in [
+            "levinion/dotfiles",
+            "https://gitlab.com/levinion/dotfiles",
+            "https://github.com/levinion",
+            "https://github.com/levinion/dotfiles/issues/1",
+            "https://raw.githubusercontent.com/levinion/dotfiles",
+        ] {
+            assert!(DownloaderBuilder::from_url(url).is_err(), "{url}");
+        }
+    }
+
+    #[test]
+    fn parses_gitmodules() {
+        let content = r#"
+[submodule "nvim"]
+	path = config/nvim
+	url = https://github.com/levinion/nvim.git
+[submodule "no url"]
+	path = lonely
+[submodule "kitty"]
+	url=../kitty.git
+	path=config/kitty
+"#;
+        let urls = gitmodules(content);
+        assert_eq!(urls.len(), 2);
+        assert_eq!(urls["config/nvim"], "https://github.com/levinion/nvim.git");
+        assert_eq!(urls["config/kitty"], "../kitty.git");
+        assert!(gitmodules("").is_empty());
+        assert!(gitmodules("not a gitmodules file").is_empty());
+    }
+
+    #[test]
+    fn resolves_submodule_repos() {
+        let remote = Remote {
+            user: "levinion".into(),
+            repo: "dotfiles".into(),
+            branch: "main".into(),
+            path: "".into(),
+            token: None,
+        };
+        let repo = |url| submodule_repo(url, &remote);
+        let expected = Some(("levinion".to_string(), "nvim".to_string()));
+        assert_eq!(repo("https://github.com/levinion/nvim.git"), expected);
+        assert_eq!(repo("git@github.com:levinion/nvim.git"), expected);
+        assert_eq!(repo("../nvim.git"), expected);
+        assert_eq!(
+            repo("../../other/nvim"),
+            Some(("other".to_string(), "nvim".to_string()))
+        );
+        assert_eq!(repo("https://github.com/"), None);
+        assert_eq!(repo("nvim"), None);
     }
 }
@@ -0,0 +1,37 @@
+use std::{collections::BTreeMap, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+/// What a download wrote, such as to `downgit.lock`, so the same files can be fetched again
+/// or checked against the remote later
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    /// Such as levinion/dotfiles
+    pub repo: String,
+    /// The ref the files were downloaded at, such as main
+    #[serde(rename = "ref")]
+    pub reference: String,
+    /// The sha of the commit the ref pointed to, when the forge tells it
+    pub commit: Option<String>,
+    /// The remote path, such as nvim
+    pub path: String,
+    /// The git blob sha of every file by its path below the remote path, the files the
+    /// listing has no sha for are left out
+    pub files: BTreeMap<String, String>,
+}
+
+impl Lockfile {
+    /// Reads the lockfile a download wrote
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let content = std::fs::read(path)?;
+        serde_json::from_slice(&content).map_err(io::Error::other)
+    }
+
+    pub(crate) async fn save(&self, path: &Path) -> io::Result<()> {
+        let content = serde_json::to_vec_pretty(self).map_err(io::Error::other)?;
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        tokio::fs::write(path, content).await
+    }
+}
@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use crate::{DownloadReport, Entry, Process};
+
+/// Watches a download, several may be registered on one downloader with
+/// [`DownloaderBuilder::observer`](crate::DownloaderBuilder::observer), such as a gui, a
+/// logger and a metrics exporter
+pub trait ProgressObserver: Send + Sync {
+    /// The tree is listed and `files` are going to be downloaded
+    fn on_start(&self, _files: usize) {}
+
+    /// A file starts downloading
+    fn on_file_start(&self, _entry: &Entry) {}
+
+    /// A file is written
+    fn on_file_done(&self, _entry: &Entry, _process: Process) {}
+
+    /// A local file is left as is, as it matches the remote one or the overwrite policy skips
+    /// it
+    fn on_file_up_to_date(&self, _entry: &Entry, _process: Process) {}
+
+    /// A file failed
+    fn on_error(&self, _entry: &Entry, _err: &anyhow::Error) {}
+
+    /// Every file is written
+    fn on_complete(&self, _report: &DownloadReport) {}
+}
+
+// the observer of a run nested in another one, such as of a submodule, which starts and
+// completes with the outer run
+pub(crate) struct Nested(pub(crate) Arc<dyn ProgressObserver>);
+
+impl ProgressObserver for Nested {
+    fn on_file_start(&self, entry: &Entry) {
+        self.0.on_file_start(entry);
+    }
+
+    fn on_file_done(&self, entry: &Entry, process: Process) {
+        self.0.on_file_done(entry, process);
+    }
+
+    fn on_file_up_to_date(&self, entry: &Entry, process: Process) {
+        self.0.on_file_up_to_date(entry, process);
+    }
+
+    fn on_error(&self, entry: &Entry, err: &anyhow::Error) {
+        self.0.on_error(entry, err);
+    }
+}
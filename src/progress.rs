@@ -0,0 +1,140 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{DownloadReport, Entry, Process, ProgressObserver, RateLimit};
+
+type TotalHandler = Arc<dyn Fn(Process) + Send + Sync>;
+
+/// How far one of the downloads reporting into a [`ProgressAggregator`] got
+#[derive(Clone, Debug)]
+pub struct JobProgress {
+    pub name: String,
+    /// The files to download, none until the tree is listed
+    pub all: usize,
+    /// The files written or left as is
+    pub current: usize,
+    pub failed: usize,
+    /// The quota last reported to the job, when the server reports one
+    pub rate_limit: Option<RateLimit>,
+    pub finished: bool,
+}
+
+impl JobProgress {
+    pub fn percent(&self) -> f64 {
+        self.current as f64 / self.all as f64
+    }
+}
+
+/// Combines the progress of several downloads running at once, such as to render a single
+/// progress bar, each download reports into it with the observer of its job
+#[derive(Clone, Default)]
+pub struct ProgressAggregator {
+    jobs: Arc<Mutex<Vec<JobProgress>>>,
+    handler: Option<TotalHandler>,
+}
+
+impl ProgressAggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Called with the combined progress whenever one of the jobs moves on, it is set
+    /// before the jobs are added as they share it
+    pub fn on_process(mut self, f: impl Fn(Process) + Send + Sync + 'static) -> Self {
+        self.handler = Some(Arc::new(f));
+        self
+    }
+
+    /// Adds a job under `name`, its download reports into the aggregate through the
+    /// observer answered, such as with `.observer(aggregator.job("nvim"))`
+    pub fn job(&self, name: impl Into<String>) -> impl ProgressObserver + 'static {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.push(JobProgress {
+            name: name.into(),
+            all: 0,
+            current: 0,
+            failed: 0,
+            rate_limit: None,
+            finished: false,
+        });
+        JobObserver {
+            aggregator: self.clone(),
+            index: jobs.len() - 1,
+        }
+    }
+
+    /// The files of every job together, with the quota closest to running out
+    pub fn total(&self) -> Process {
+        total(&self.jobs.lock().unwrap())
+    }
+
+    /// The progress of each job, in the order they were added
+    pub fn jobs(&self) -> Vec<JobProgress> {
+        self.jobs.lock().unwrap().clone()
+    }
+
+    fn update(&self, index: usize, f: impl FnOnce(&mut JobProgress)) {
+        let total = {
+            let mut jobs = self.jobs.lock().unwrap();
+            f(&mut jobs[index]);
+            total(&jobs)
+        };
+        if let Some(handler) = &self.handler {
+            handler(total);
+        }
+    }
+}
+
+fn total(jobs: &[JobProgress]) -> Process {
+    Process {
+        current: jobs.iter().map(|job| job.current).sum(),
+        all: jobs.iter().map(|job| job.all).sum(),
+        rate_limit: jobs
+            .iter()
+            .filter_map(|job| job.rate_limit)
+            .min_by_key(|rate_limit| rate_limit.remaining),
+    }
+}
+
+struct JobObserver {
+    aggregator: ProgressAggregator,
+    index: usize,
+}
+
+impl JobObserver {
+    fn progressed(&self, process: Process) {
+        self.aggregator.update(self.index, |job| {
+            job.current = process.current;
+            job.all = process.all;
+            job.rate_limit = process.rate_limit.or(job.rate_limit);
+        });
+    }
+}
+
+impl ProgressObserver for JobObserver {
+    // a job run again, such as by a watch, starts over
+    fn on_start(&self, files: usize) {
+        self.aggregator.update(self.index, |job| {
+            job.all = files;
+            job.current = 0;
+            job.failed = 0;
+            job.finished = false;
+        });
+    }
+
+    fn on_file_done(&self, _entry: &Entry, process: Process) {
+        self.progressed(process);
+    }
+
+    fn on_file_up_to_date(&self, _entry: &Entry, process: Process) {
+        self.progressed(process);
+    }
+
+    fn on_error(&self, _entry: &Entry, _err: &anyhow::Error) {
+        self.aggregator.update(self.index, |job| job.failed += 1);
+    }
+
+    fn on_complete(&self, _report: &DownloadReport) {
+        self.aggregator
+            .update(self.index, |job| job.finished = true);
+    }
+}
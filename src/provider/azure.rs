@@ -0,0 +1,226 @@
+use anyhow::Result;
+use reqwest::{Client, RequestBuilder, Url};
+
+use super::{api_base, async_trait, base_url, host_of, Provider};
+use crate::{rate_limit::send, Error, Node, Remote};
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Item {
+    path: String,
+    git_object_type: String,
+    object_id: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Items {
+    value: Vec<Item>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Ref {
+    name: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Refs {
+    value: Vec<Ref>,
+}
+
+/// Azure DevOps Repos, the user is the organization and the repo lives in `project`
+pub struct AzureDevOps {
+    host: String,
+    base: Url,
+    project: String,
+}
+
+impl AzureDevOps {
+    /// The host of Azure DevOps Services
+    pub const HOST: &'static str = "https://dev.azure.com";
+    const API_VERSION: &'static str = "7.1";
+
+    /// Refuses a host that isn't a url, such as [`AzureDevOps::HOST`] or the collection
+    /// url of an Azure DevOps Server, `https://tfs.mycorp.com/tfs`
+    pub fn new(host: &str, project: &str) -> Result<Self, Error> {
+        let host = base_url(host);
+        let base = api_base(&host, "azure devops host")?;
+        Ok(Self {
+            host,
+            base,
+            project: project.into(),
+        })
+    }
+
+    // a commit sha, a tag given as refs/tags/<name>, or else a branch
+    fn version(reference: &str) -> (&'static str, &str) {
+        if let Some(tag) = reference.strip_prefix("refs/tags/") {
+            return ("tag", tag);
+        }
+        let sha = reference.len() == 40 && reference.chars().all(|c| c.is_ascii_hexdigit());
+        match sha {
+            true => ("commit", reference),
+            false => ("branch", reference),
+        }
+    }
+
+    // whether the repo has the full ref `name`, such as refs/heads/main
+    async fn has_ref(&self, client: &Client, remote: &Remote, name: &str) -> Result<bool> {
+        let mut url = self.repo(remote);
+        url.path_segments_mut().unwrap().push("refs");
+        url.query_pairs_mut()
+            .append_pair("filter", name.trim_start_matches("refs/"))
+            .append_pair("api-version", Self::API_VERSION);
+        let res = send(self.get(client, remote, url)).await?;
+        // a missing repo is reported by the listing
+        if !res.status().is_success() {
+            return Ok(false);
+        }
+        let refs: Refs = serde_json::from_slice(&res.bytes().await?)?;
+        // the filter matches prefixes, such as refs/heads/main-old for heads/main
+        Ok(refs.value.iter().any(|found| found.name == name))
+    }
+
+    fn repo(&self, remote: &Remote) -> Url {
+        let mut url = self.base.clone();
+        // the base was checked to take path segments
+        url.path_segments_mut().unwrap().extend([
+            &remote.user,
+            &self.project,
+            "_apis",
+            "git",
+            "repositories",
+            &remote.repo,
+        ]);
+        url
+    }
+
+    fn url(&self, remote: &Remote) -> Url {
+        let mut url = self.repo(remote);
+        url.path_segments_mut().unwrap().push("items");
+        let (kind, version) = Self::version(&remote.branch);
+        url.query_pairs_mut()
+            .append_pair("versionDescriptor.version", version)
+            .append_pair("versionDescriptor.versionType", kind)
+            .append_pair("api-version", Self::API_VERSION);
+        url
+    }
+
+    // personal access tokens are sent as the password of basic auth with an empty user
+    fn get(&self, client: &Client, remote: &Remote, url: Url) -> RequestBuilder {
+        let req = client.get(url);
+        match &remote.token {
+            Some(token) => req.basic_auth("", Some(token)),
+            None => req,
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for AzureDevOps {
+    fn token_env(&self) -> Option<&'static str> {
+        Some("AZURE_DEVOPS_EXT_PAT")
+    }
+
+    fn host(&self) -> Option<String> {
+        host_of(&self.host)
+    }
+
+    // the items api is told whether the version is a branch, a tag or a commit, a name
+    // matching no branch is looked up among the tags
+    async fn resolve(&self, client: &Client, remote: &Remote) -> Result<String> {
+        let reference = &remote.branch;
+        if let Some(branch) = reference.strip_prefix("refs/heads/") {
+            return Ok(branch.to_string());
+        }
+        if Self::version(reference).0 != "branch" {
+            return Ok(reference.clone());
+        }
+        let tag = format!("refs/tags/{reference}");
+        let branch = self
+            .has_ref(client, remote, &format!("refs/heads/{reference}"))
+            .await?;
+        match !branch && self.has_ref(client, remote, &tag).await? {
+            true => Ok(tag),
+            false => Ok(reference.clone()),
+        }
+    }
+
+    async fn tree(&self, client: &Client, remote: &Remote) -> Result<Vec<Node>> {
+        let mut url = self.url(remote);
+        url.query_pairs_mut().append_pair("recursionLevel", "Full");
+        let res = send(self.get(client, remote, url)).await?.bytes().await?;
+        let items: Items =
+            serde_json::from_slice(&res).map_err(|_| Error::repo_not_found(remote))?;
+        let tree = items
+            .value
+            .into_iter()
+            .map(|item| Node {
+                path: item.path.trim_start_matches('/').into(),
+                kind: item.git_object_type,
+                size: None,
+                sha: item.object_id,
+                mode: None,
+            })
+            .collect();
+        Ok(tree)
+    }
+
+    fn raw(&self, client: &Client, remote: &Remote, path: &str) -> RequestBuilder {
+        let mut url = self.url(remote);
+        url.query_pairs_mut()
+            .append_pair("path", path)
+            .append_pair("download", "true")
+            .append_pair("$format", "octetStream");
+        self.get(client, remote, url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stub::{Response, Stub};
+
+    fn remote(branch: &str) -> Remote {
+        Remote {
+            user: "levinion".into(),
+            repo: "dotfiles".into(),
+            branch: branch.into(),
+            path: "".into(),
+            token: None,
+        }
+    }
+
+    #[test]
+    fn tells_the_version_type() {
+        let sha = "0123456789abcdef0123456789abcdef01234567";
+        assert_eq!(AzureDevOps::version("main"), ("branch", "main"));
+        assert_eq!(AzureDevOps::version("refs/tags/v1.0"), ("tag", "v1.0"));
+        assert_eq!(AzureDevOps::version(sha), ("commit", sha));
+    }
+
+    #[tokio::test]
+    async fn resolves_a_tag_matching_no_branch() {
+        let stub = Stub::start(|req| match req.path.contains("filter=tags%2Fv1.0") {
+            true => Response::ok(r#"{"value": [{"name": "refs/tags/v1.0"}]}"#),
+            false => Response::ok(r#"{"value": [{"name": "refs/heads/v1.0-fixes"}]}"#),
+        })
+        .await;
+        let azure = AzureDevOps::new(&stub.url, "home").unwrap();
+        let client = Client::new();
+        let tag = azure.resolve(&client, &remote("v1.0")).await.unwrap();
+        assert_eq!(tag, "refs/tags/v1.0");
+        let url = azure.url(&remote(&tag));
+        let query: Vec<_> = url.query_pairs().collect();
+        assert!(query.contains(&("versionDescriptor.version".into(), "v1.0".into())));
+        assert!(query.contains(&("versionDescriptor.versionType".into(), "tag".into())));
+        assert!(url
+            .path()
+            .starts_with("/levinion/home/_apis/git/repositories/dotfiles/"));
+    }
+
+    #[test]
+    fn refuses_a_host_that_is_no_url() {
+        assert!(AzureDevOps::new(AzureDevOps::HOST, "home").is_ok());
+        assert!(AzureDevOps::new("https://[tfs", "home").is_err());
+    }
+}
@@ -0,0 +1,226 @@
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use reqwest::{Client, RequestBuilder};
+
+use super::{async_trait, Bytes, Provider};
+use crate::{
+    sigv4::{payload_hash, Credentials},
+    Error, Node, Remote,
+};
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    absolute_path: String,
+    blob_id: Option<String>,
+    file_mode: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SubModule {
+    absolute_path: String,
+    commit_id: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Folder {
+    sub_folders: Vec<Entry>,
+    files: Vec<Entry>,
+    #[serde(default)]
+    symbolic_links: Vec<Entry>,
+    #[serde(default)]
+    sub_modules: Vec<SubModule>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct File {
+    file_content: String,
+}
+
+impl Folder {
+    // the files, links and submodules in the folder, links and submodules being listed
+    // apart from the files
+    fn entries(self) -> impl Iterator<Item = Node> {
+        let node = |path: String, kind: &str, sha, mode: &str| Node {
+            path: path.trim_start_matches('/').into(),
+            kind: kind.into(),
+            size: None,
+            sha,
+            mode: Some(mode.into()),
+        };
+        let files = self.files.into_iter().map(move |entry| {
+            let mode = match entry.file_mode.as_deref() {
+                Some("EXECUTABLE") => "100755",
+                Some("SYMLINK") => "120000",
+                _ => "100644",
+            };
+            node(entry.absolute_path, "blob", entry.blob_id, mode)
+        });
+        let links = self
+            .symbolic_links
+            .into_iter()
+            .map(move |entry| node(entry.absolute_path, "blob", entry.blob_id, "120000"));
+        let modules = self
+            .sub_modules
+            .into_iter()
+            .map(move |module| node(module.absolute_path, "commit", module.commit_id, "160000"));
+        files.chain(links).chain(modules)
+    }
+}
+
+/// AWS CodeCommit, requests are signed with SigV4 and only the repo name is used
+pub struct CodeCommit {
+    region: String,
+    credentials: Option<Credentials>,
+}
+
+impl CodeCommit {
+    const SERVICE: &'static str = "codecommit";
+    const TARGET_PREFIX: &'static str = "CodeCommit_20150413";
+
+    /// Credentials are taken from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+    /// `AWS_SESSION_TOKEN` unless given with [`CodeCommit::credentials`]
+    pub fn new(region: &str) -> Self {
+        Self {
+            region: region.into(),
+            credentials: Credentials::from_env(),
+        }
+    }
+
+    pub fn credentials(
+        mut self,
+        access_key: &str,
+        secret_key: &str,
+        session_token: Option<&str>,
+    ) -> Self {
+        self.credentials = Some(Credentials::new(access_key, secret_key, session_token));
+        self
+    }
+
+    fn host(&self) -> String {
+        format!("{}.{}.amazonaws.com", Self::SERVICE, self.region)
+    }
+
+    // codecommit is a json rpc api, every call is a signed POST to / naming its action
+    fn call(&self, client: &Client, action: &str, body: serde_json::Value) -> RequestBuilder {
+        let body = body.to_string();
+        let host = self.host();
+        let target = format!("{}.{action}", Self::TARGET_PREFIX);
+        let req = client
+            .post(format!("https://{host}/"))
+            .header("Content-Type", "application/x-amz-json-1.1")
+            .header("X-Amz-Target", &target);
+        let Some(credentials) = &self.credentials else {
+            return req.body(body);
+        };
+        let headers = vec![
+            ("content-type", "application/x-amz-json-1.1".to_string()),
+            ("x-amz-target", target),
+        ];
+        let headers = credentials.sign(
+            &self.region,
+            Self::SERVICE,
+            "POST",
+            &host,
+            "/",
+            headers,
+            &payload_hash(body.as_bytes()),
+        );
+        headers
+            .into_iter()
+            .fold(req, |req, (name, value)| req.header(name, value))
+            .body(body)
+    }
+}
+
+#[async_trait]
+impl Provider for CodeCommit {
+    // only folders leading to or below the remote path are walked, as GetFolder isn't recursive
+    async fn tree(&self, client: &Client, remote: &Remote) -> Result<Vec<Node>> {
+        let mut tree = vec![];
+        let mut dirs = vec![String::from("/")];
+        while let Some(dir) = dirs.pop() {
+            let body = serde_json::json!({
+                "repositoryName": remote.repo,
+                "commitSpecifier": remote.branch,
+                "folderPath": dir,
+            });
+            let res = self
+                .call(client, "GetFolder", body)
+                .send()
+                .await?
+                .bytes()
+                .await?;
+            let mut folder: Folder =
+                serde_json::from_slice(&res).map_err(|_| Error::repo_not_found(remote))?;
+            for entry in std::mem::take(&mut folder.sub_folders) {
+                let path = entry.absolute_path.trim_start_matches('/').to_string();
+                if path.starts_with(&remote.path) || remote.path.starts_with(&path) {
+                    dirs.push(path.clone());
+                }
+                tree.push(Node {
+                    path,
+                    kind: "tree".into(),
+                    size: None,
+                    sha: None,
+                    mode: None,
+                });
+            }
+            tree.extend(folder.entries());
+        }
+        Ok(tree)
+    }
+
+    fn raw(&self, client: &Client, remote: &Remote, path: &str) -> RequestBuilder {
+        let body = serde_json::json!({
+            "repositoryName": remote.repo,
+            "commitSpecifier": remote.branch,
+            "filePath": path,
+        });
+        self.call(client, "GetFile", body)
+    }
+
+    fn buffered(&self) -> bool {
+        true
+    }
+
+    // GetFile answers with json carrying the base64 encoded content
+    fn decode(&self, body: Bytes) -> Result<Bytes> {
+        let file: File = serde_json::from_slice(&body)
+            .map_err(|_| anyhow!(String::from_utf8_lossy(&body).into_owned()))?;
+        let content = base64::engine::general_purpose::STANDARD.decode(file.file_content)?;
+        Ok(content.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_links_and_submodules() {
+        let folder: Folder = serde_json::from_str(
+            r#"{
+                "subFolders": [{"absolutePath": "/nvim/lua"}],
+                "files": [{"absolutePath": "/nvim/run.sh", "blobId": "a", "fileMode": "EXECUTABLE"}],
+                "symbolicLinks": [{"absolutePath": "/nvim/init.vim", "blobId": "b", "fileMode": "SYMLINK"}],
+                "subModules": [{"absolutePath": "/nvim/pack", "commitId": "c"}]
+            }"#,
+        )
+        .unwrap();
+        let nodes: Vec<_> = folder
+            .entries()
+            .map(|node| (node.path, node.kind, node.sha.unwrap(), node.mode.unwrap()))
+            .collect();
+        let expected = [
+            ("nvim/run.sh", "blob", "a", "100755"),
+            ("nvim/init.vim", "blob", "b", "120000"),
+            ("nvim/pack", "commit", "c", "160000"),
+        ]
+        .map(|(path, kind, sha, mode)| (path.into(), kind.into(), sha.into(), mode.into()));
+        assert_eq!(nodes, expected);
+    }
+}
@@ -0,0 +1,95 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::{anyhow, Result};
+use reqwest::{Client, RequestBuilder};
+
+use super::{async_trait, Provider};
+use crate::{rate_limit::send, Node, Remote};
+
+#[derive(serde::Deserialize, Debug)]
+struct File {
+    filename: String,
+    raw_url: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct GistFiles {
+    files: HashMap<String, File>,
+}
+
+/// GitHub Gists, the repo is the gist id and every file sits at the root
+#[derive(Default)]
+pub struct Gist {
+    revision: Option<String>,
+    files: Vec<String>,
+    // gist files are fetched from the raw urls handed out by the listing
+    raw_urls: Mutex<HashMap<String, String>>,
+}
+
+impl Gist {
+    const API: &'static str = "https://api.github.com";
+
+    /// Download the gist as of revision `sha` instead of its latest state
+    pub fn revision(mut self, sha: &str) -> Self {
+        self.revision = Some(sha.into());
+        self
+    }
+
+    /// Download only these files of the gist
+    pub fn files(mut self, files: &[&str]) -> Self {
+        self.files = files.iter().map(|file| file.to_string()).collect();
+        self
+    }
+
+    fn get(&self, client: &Client, remote: &Remote, url: String) -> RequestBuilder {
+        let req = client.get(url);
+        match &remote.token {
+            Some(token) => req.bearer_auth(token),
+            None => req,
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for Gist {
+    fn token_env(&self) -> Option<&'static str> {
+        Some("GITHUB_TOKEN")
+    }
+
+    fn host(&self) -> Option<String> {
+        Some("github.com".into())
+    }
+
+    async fn tree(&self, client: &Client, remote: &Remote) -> Result<Vec<Node>> {
+        let url = match &self.revision {
+            Some(sha) => format!("{}/gists/{}/{sha}", Self::API, remote.repo),
+            None => format!("{}/gists/{}", Self::API, remote.repo),
+        };
+        let res = send(self.get(client, remote, url)).await?.bytes().await?;
+        let gist: GistFiles = serde_json::from_slice(&res)
+            .map_err(|_| anyhow!("Are you sure the gist really exists?"))?;
+        let mut raw_urls = self.raw_urls.lock().unwrap();
+        let tree = gist
+            .files
+            .into_values()
+            .filter(|file| self.files.is_empty() || self.files.contains(&file.filename))
+            .map(|file| {
+                let node = Node::blob(&file.filename);
+                raw_urls.insert(file.filename, file.raw_url);
+                node
+            })
+            .collect();
+        Ok(tree)
+    }
+
+    fn raw(&self, client: &Client, remote: &Remote, path: &str) -> RequestBuilder {
+        let url = self.raw_urls.lock().unwrap().get(path).cloned();
+        let url = url.unwrap_or_else(|| {
+            format!(
+                "https://gist.githubusercontent.com/{}/{}/raw/{path}",
+                remote.user, remote.repo
+            )
+        });
+        self.get(client, remote, url)
+    }
+}
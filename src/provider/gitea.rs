@@ -0,0 +1,219 @@
+use anyhow::{anyhow, Result};
+use reqwest::{Client, RequestBuilder, Response, StatusCode, Url};
+
+use super::{api_base, async_trait, base_url, host_of, lfs_batch, Provider};
+use crate::{rate_limit::send, Error, Node, Remote};
+
+#[derive(serde::Deserialize, Debug)]
+struct Content {
+    path: String,
+    #[serde(rename = "type")]
+    kind: String,
+    size: Option<u64>,
+    sha: String,
+}
+
+// a directory answers its entries, a file itself
+#[derive(serde::Deserialize, Debug)]
+#[serde(untagged)]
+enum Contents {
+    Dir(Vec<Content>),
+    File(Content),
+}
+
+/// Gitea compatible forges, such as Forgejo and Codeberg
+pub struct Gitea {
+    host: String,
+    base: Url,
+}
+
+impl Gitea {
+    /// Refuses a host that isn't a url, such as `https://codeberg.org` or `codeberg.org`
+    pub fn new(host: &str) -> Result<Self, Error> {
+        let host = base_url(host);
+        let base = api_base(&host, "gitea host")?;
+        Ok(Self { host, base })
+    }
+
+    fn url(&self, remote: &Remote, segments: &[&str]) -> Url {
+        let mut url = self.base.clone();
+        // the base was checked to take path segments
+        url.path_segments_mut()
+            .unwrap()
+            .extend(["api", "v1", "repos", &remote.user, &remote.repo])
+            .extend(segments);
+        url
+    }
+
+    // why the api refused to answer, a missing repo, ref and path all answer 404, the repo
+    // and the commit are looked up to tell them apart
+    async fn refused(&self, client: &Client, remote: &Remote, res: &Response) -> anyhow::Error {
+        if let Some(err) = Error::rate_limited(res) {
+            return err.into();
+        }
+        match res.error_for_status_ref() {
+            Err(_) if res.status() == StatusCode::NOT_FOUND => {}
+            Err(err) => return err.into(),
+            Ok(res) => return anyhow!("The api answered {}", res.status()),
+        }
+        let found = |res: Result<Response>| res.is_ok_and(|res| res.status().is_success());
+        if !found(send(self.get(client, remote, self.url(remote, &[]))).await) {
+            return Error::repo_not_found(remote).into();
+        }
+        let commit = self.url(remote, &["git", "commits", &remote.branch]);
+        match found(send(self.get(client, remote, commit)).await) {
+            true => Error::PathNotFound(remote.path.clone()).into(),
+            false => Error::BranchNotFound(remote.branch.clone()).into(),
+        }
+    }
+
+    fn get(&self, client: &Client, remote: &Remote, url: Url) -> RequestBuilder {
+        let req = client.get(url);
+        match &remote.token {
+            Some(token) => req.header("Authorization", format!("token {token}")),
+            None => req,
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for Gitea {
+    fn token_env(&self) -> Option<&'static str> {
+        Some("GITEA_TOKEN")
+    }
+
+    fn host(&self) -> Option<String> {
+        host_of(&self.host)
+    }
+
+    // walks the contents api directory by directory below the remote path, so only the
+    // remote path is listed rather than the whole repo
+    async fn tree(&self, client: &Client, remote: &Remote) -> Result<Vec<Node>> {
+        let mut tree = vec![];
+        let mut dirs = vec![remote.path.trim_matches('/').to_string()];
+        while let Some(dir) = dirs.pop() {
+            let mut url = self.url(remote, &["contents"]);
+            url.path_segments_mut()
+                .unwrap()
+                .extend(dir.split('/').filter(|part| !part.is_empty()));
+            url.query_pairs_mut().append_pair("ref", &remote.branch);
+            let res = send(self.get(client, remote, url)).await?;
+            if !res.status().is_success() {
+                return Err(self.refused(client, remote, &res).await);
+            }
+            let contents = match serde_json::from_slice(&res.bytes().await?)? {
+                Contents::Dir(contents) => contents,
+                Contents::File(content) => vec![content],
+            };
+            for content in contents {
+                let (kind, mode) = match content.kind.as_str() {
+                    "dir" => ("tree", None),
+                    "file" => ("blob", None),
+                    "symlink" => ("blob", Some("120000".into())),
+                    "submodule" => ("commit", Some("160000".into())),
+                    _ => continue,
+                };
+                if kind == "tree" {
+                    dirs.push(content.path.clone());
+                }
+                tree.push(Node {
+                    path: content.path,
+                    kind: kind.into(),
+                    size: content.size,
+                    sha: Some(content.sha),
+                    mode,
+                });
+            }
+        }
+        Ok(tree)
+    }
+
+    fn archive(&self, client: &Client, remote: &Remote) -> Option<RequestBuilder> {
+        let url = self.url(remote, &["archive", &format!("{}.tar.gz", remote.branch)]);
+        Some(self.get(client, remote, url))
+    }
+
+    fn lfs(&self, client: &Client, remote: &Remote) -> Option<RequestBuilder> {
+        Some(lfs_batch(client, &self.host, remote, &remote.user))
+    }
+
+    fn raw(&self, client: &Client, remote: &Remote, path: &str) -> RequestBuilder {
+        let mut url = self.url(remote, &["raw"]);
+        url.path_segments_mut().unwrap().extend(path.split('/'));
+        url.query_pairs_mut().append_pair("ref", &remote.branch);
+        self.get(client, remote, url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stub::{Response, Stub};
+
+    fn remote(path: &str) -> Remote {
+        Remote {
+            user: "levinion".into(),
+            repo: "dotfiles".into(),
+            branch: "main".into(),
+            path: path.into(),
+            token: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn walks_the_contents_of_the_remote_path() {
+        let stub = Stub::start(|req| match req.path.as_str() {
+            "/api/v1/repos/levinion/dotfiles/contents/nvim?ref=main" => Response::ok(
+                r#"[{"path": "nvim/lua", "type": "dir", "size": 0, "sha": "a"},
+                    {"path": "nvim/init.lua", "type": "symlink", "size": 9, "sha": "b"},
+                    {"path": "nvim/pack", "type": "submodule", "size": 0, "sha": "c"}]"#,
+            ),
+            "/api/v1/repos/levinion/dotfiles/contents/nvim/lua?ref=main" => Response::ok(
+                r#"[{"path": "nvim/lua/opts.lua", "type": "file", "size": 5, "sha": "d"}]"#,
+            ),
+            _ => Response::new(404),
+        })
+        .await;
+        let gitea = Gitea::new(&stub.url).unwrap();
+        let tree = gitea.tree(&Client::new(), &remote("nvim")).await.unwrap();
+        let nodes: Vec<_> = tree
+            .iter()
+            .map(|node| (node.path.as_str(), node.kind.as_str(), node.mode.as_deref()))
+            .collect();
+        assert_eq!(
+            nodes,
+            [
+                ("nvim/lua", "tree", None),
+                ("nvim/init.lua", "blob", Some("120000")),
+                ("nvim/pack", "commit", Some("160000")),
+                ("nvim/lua/opts.lua", "blob", None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn tells_what_is_missing() {
+        let stub = Stub::start(|req| match req.path.as_str() {
+            "/api/v1/repos/levinion/dotfiles" => Response::ok("{}"),
+            "/api/v1/repos/levinion/dotfiles/git/commits/main" => Response::ok("{}"),
+            _ => Response::new(404),
+        })
+        .await;
+        let gitea = Gitea::new(&stub.url).unwrap();
+        let err = gitea
+            .tree(&Client::new(), &remote("gone"))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(Error::PathNotFound(_))),
+            "{err:?}"
+        );
+        let stub = Stub::start(|_| Response::new(404)).await;
+        let gitea = Gitea::new(&stub.url).unwrap();
+        let err = gitea.tree(&Client::new(), &remote("")).await.unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(Error::RepoNotFound { .. })),
+            "{err:?}"
+        );
+    }
+}
@@ -0,0 +1,655 @@
+use std::{fmt, io::BufReader};
+
+use anyhow::{anyhow, Result};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+
+use super::{async_trait, base_url, graphql, host_of, lfs_batch, Provider};
+use crate::{
+    auth::{gh_token, stored_token},
+    rate_limit::send,
+    stream::read_blocking,
+    Change, Error, Node, Remote,
+};
+
+// a directory whose listing is cut short even one level deep
+#[derive(Debug, thiserror::Error)]
+#[error("The directory {0} is too large to list")]
+struct TooLarge(String);
+
+#[derive(Debug)]
+struct FileTree {
+    tree: Vec<Node>,
+    truncated: bool,
+}
+
+// deserializes a tree listing keeping only the entries related to path, the others are
+// dropped as they are parsed instead of after the whole listing is held
+struct Listing<'a> {
+    path: &'a str,
+}
+
+impl<'de> DeserializeSeed<'de> for Listing<'_> {
+    type Value = FileTree;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<FileTree, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Listing<'_> {
+    type Value = FileTree;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a git tree")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<FileTree, A::Error> {
+        let (mut tree, mut truncated) = (None, false);
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "tree" => tree = Some(map.next_value_seed(Entries { path: self.path })?),
+                "truncated" => truncated = map.next_value()?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        let tree = tree.ok_or_else(|| de::Error::missing_field("tree"))?;
+        Ok(FileTree { tree, truncated })
+    }
+}
+
+struct Entries<'a> {
+    path: &'a str,
+}
+
+impl<'de> DeserializeSeed<'de> for Entries<'_> {
+    type Value = Vec<Node>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Vec<Node>, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Entries<'_> {
+    type Value = Vec<Node>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("tree entries")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<Node>, A::Error> {
+        let mut tree = vec![];
+        while let Some(node) = seq.next_element::<Node>()? {
+            if node.path.starts_with(self.path) || self.path.starts_with(&node.path) {
+                tree.push(node);
+            }
+        }
+        Ok(tree)
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Content {
+    path: String,
+    #[serde(rename = "type")]
+    kind: String,
+    size: Option<u64>,
+    sha: String,
+}
+
+// a directory answers its entries, a file itself
+#[derive(serde::Deserialize, Debug)]
+#[serde(untagged)]
+enum Contents {
+    Dir(Vec<Content>),
+    File(Content),
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Head {
+    sha: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct PullRequest {
+    head: Head,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Comparison {
+    #[serde(default)]
+    files: Vec<ChangedFile>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct ChangedFile {
+    filename: String,
+    status: String,
+    sha: Option<String>,
+    previous_filename: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Commit {
+    sha: String,
+}
+
+pub struct GitHub {
+    api: String,
+    raw: String,
+    graphql: bool,
+}
+
+impl Default for GitHub {
+    fn default() -> Self {
+        Self::new(
+            "https://api.github.com",
+            "https://raw.githubusercontent.com",
+        )
+    }
+}
+
+impl GitHub {
+    const CODELOAD: &'static str = "https://codeload.github.com";
+    const MAX_COMPARED: usize = 300;
+
+    // enterprise servers use such as https://github.mycorp.com/api/v3 and https://github.mycorp.com/raw
+    pub fn new(api: &str, raw: &str) -> Self {
+        Self {
+            api: base_url(api),
+            raw: base_url(raw),
+            graphql: false,
+        }
+    }
+
+    /// Lists the tree through the GraphQL api, with the sizes and shas of the entries
+    /// in fewer requests, it needs a token and works better with fine-grained ones
+    pub fn graphql(mut self) -> Self {
+        self.graphql = true;
+        self
+    }
+
+    // such as https://api.github.com/graphql, or https://github.mycorp.com/api/graphql
+    // for https://github.mycorp.com/api/v3
+    fn graphql_url(&self) -> String {
+        let api = self.api.strip_suffix("/v3").unwrap_or(&self.api);
+        format!("{api}/graphql")
+    }
+
+    // such as pull/123/head, pull/123 or #123
+    fn pull_number(reference: &str) -> Option<u64> {
+        let number = reference
+            .strip_prefix("refs/")
+            .unwrap_or(reference)
+            .strip_prefix("pull/")
+            .map(|rest| rest.trim_end_matches("/head"))
+            .or_else(|| reference.strip_prefix('#'))?;
+        number.parse().ok()
+    }
+
+    // full refs and what may be abbreviated shas are resolved to the commit, such as
+    // refs/tags/v1.2.3 into tags/v1.2.3 for the commits endpoint
+    fn commit_ref(reference: &str) -> Option<String> {
+        if let Some(name) = reference.strip_prefix("refs/") {
+            return Some(name.to_string());
+        }
+        let hex = reference.chars().all(|c| c.is_ascii_hexdigit());
+        (hex && (7..40).contains(&reference.len())).then(|| reference.to_string())
+    }
+
+    // the entries related to path of the tree at a ref or tree sha, with the paths relative
+    // to it, monorepo listings of tens of megabytes are parsed as they stream in
+    async fn git_tree(
+        &self,
+        client: &Client,
+        remote: &Remote,
+        sha: &str,
+        path: &str,
+        recursive: bool,
+    ) -> Result<FileTree> {
+        let mut url = format!(
+            "{}/repos/{}/{}/git/trees/{sha}",
+            &self.api, &remote.user, &remote.repo
+        );
+        if recursive {
+            url.push_str("?recursive=1");
+        }
+        let res = send(self.get(client, remote, url)).await?;
+        if !res.status().is_success() {
+            return Err(self.refused(client, remote, &res).await);
+        }
+        let path = path.to_string();
+        read_blocking(res, move |reader| {
+            let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(reader));
+            Listing { path: &path }
+                .deserialize(&mut deserializer)
+                .map_err(Into::into)
+        })
+        .await
+    }
+
+    async fn trees(&self, client: &Client, remote: &Remote) -> Result<Vec<Node>> {
+        let file_tree = self
+            .git_tree(client, remote, &remote.branch, &remote.path, true)
+            .await?;
+        if !file_tree.truncated {
+            return Ok(file_tree.tree);
+        }
+        // the recursive listing of very large repos is cut short, the directories related
+        // to the remote path are listed one level at a time until their subtrees fit
+        let mut tree = vec![];
+        let mut pending = vec![(String::new(), remote.branch.clone(), false)];
+        while let Some((prefix, sha, recursive)) = pending.pop() {
+            // the remote path relative to the listed directory, all of it below the path
+            let path = remote
+                .path
+                .strip_prefix(&prefix)
+                .map(|path| path.trim_start_matches('/'))
+                .unwrap_or_default();
+            let file_tree = self.git_tree(client, remote, &sha, path, recursive).await?;
+            match (file_tree.truncated, recursive) {
+                (true, true) => {
+                    pending.push((prefix, sha, false));
+                    continue;
+                }
+                (true, false) => return Err(TooLarge(prefix).into()),
+                _ => {}
+            }
+            for mut node in file_tree.tree {
+                if !prefix.is_empty() {
+                    node.path = format!("{prefix}/{}", node.path);
+                }
+                let related =
+                    node.path.starts_with(&remote.path) || remote.path.starts_with(&node.path);
+                if let (false, "tree", true, Some(sha)) =
+                    (recursive, node.kind.as_str(), related, &node.sha)
+                {
+                    pending.push((node.path.clone(), sha.clone(), true));
+                }
+                tree.push(node);
+            }
+        }
+        Ok(tree)
+    }
+
+    // walks the contents api directory by directory below the remote path, slower than
+    // the trees api but answered where it isn't
+    async fn contents(&self, client: &Client, remote: &Remote) -> Result<Vec<Node>> {
+        let mut tree = vec![];
+        let mut dirs = vec![remote.path.trim_end_matches('/').to_string()];
+        while let Some(dir) = dirs.pop() {
+            let url = format!(
+                "{}/repos/{}/{}/contents/{dir}?ref={}",
+                &self.api, &remote.user, &remote.repo, &remote.branch
+            );
+            let res = send(self.get(client, remote, url)).await?;
+            if !res.status().is_success() {
+                return Err(self.refused(client, remote, &res).await);
+            }
+            let contents: Contents = serde_json::from_slice(&res.bytes().await?)?;
+            let contents = match contents {
+                Contents::Dir(contents) => contents,
+                Contents::File(content) => vec![content],
+            };
+            for content in contents {
+                let kind = match content.kind.as_str() {
+                    "dir" => "tree",
+                    "file" | "symlink" => "blob",
+                    _ => continue,
+                };
+                if kind == "tree" {
+                    dirs.push(content.path.clone());
+                }
+                // the contents api only tells symlinks apart
+                let mode = (content.kind == "symlink").then(|| "120000".into());
+                tree.push(Node {
+                    path: content.path,
+                    kind: kind.into(),
+                    size: content.size,
+                    sha: Some(content.sha),
+                    mode,
+                });
+            }
+        }
+        Ok(tree)
+    }
+
+    // empty repos answer 409 and trees too large to walk 422 or a listing cut short, the
+    // contents api still lists those, other failures are passed on
+    fn unlisted(err: &anyhow::Error) -> bool {
+        let status = err
+            .downcast_ref::<reqwest::Error>()
+            .and_then(reqwest::Error::status);
+        let refused = matches!(
+            status,
+            Some(StatusCode::CONFLICT | StatusCode::UNPROCESSABLE_ENTITY)
+        );
+        refused || err.is::<TooLarge>()
+    }
+
+    // why the api refused to list the tree, a missing repo and a missing ref both answer
+    // 404, the repo itself is looked up to tell them apart
+    async fn refused(&self, client: &Client, remote: &Remote, res: &Response) -> anyhow::Error {
+        if let Some(err) = Error::rate_limited(res) {
+            return err.into();
+        }
+        match res.error_for_status_ref() {
+            Err(_) if res.status() == StatusCode::NOT_FOUND => {}
+            Err(err) => return err.into(),
+            Ok(res) => return anyhow!("The api answered {}", res.status()),
+        }
+        let url = format!("{}/repos/{}/{}", &self.api, &remote.user, &remote.repo);
+        match send(self.get(client, remote, url)).await {
+            Ok(res) if res.status().is_success() => {
+                Error::BranchNotFound(remote.branch.clone()).into()
+            }
+            _ => Error::repo_not_found(remote).into(),
+        }
+    }
+
+    // a commit or pull request, none when the api answers there is no such one, with 404
+    // or 422 for a sha matching no commit, other failures are passed on
+    async fn lookup<T: de::DeserializeOwned>(
+        &self,
+        client: &Client,
+        remote: &Remote,
+        url: String,
+    ) -> Result<Option<T>> {
+        let res = send(self.get(client, remote, url)).await?;
+        if let Some(err) = Error::rate_limited(&res) {
+            return Err(err.into());
+        }
+        if matches!(
+            res.status(),
+            StatusCode::NOT_FOUND | StatusCode::UNPROCESSABLE_ENTITY
+        ) {
+            return Ok(None);
+        }
+        let res = res.error_for_status()?;
+        Ok(Some(serde_json::from_slice(&res.bytes().await?)?))
+    }
+
+    fn get(&self, client: &Client, remote: &Remote, url: String) -> RequestBuilder {
+        let req = client.get(url);
+        match &remote.token {
+            Some(token) => req.bearer_auth(token),
+            None => req,
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for GitHub {
+    fn token_env(&self) -> Option<&'static str> {
+        Some("GITHUB_TOKEN")
+    }
+
+    // the web host the api serves, such as github.com for api.github.com
+    fn host(&self) -> Option<String> {
+        let host = host_of(&self.api)?;
+        Some(host.strip_prefix("api.").unwrap_or(&host).to_string())
+    }
+
+    // same lookup as the gh cli: environment first, then its hosts.yml, then keyring and netrc
+    fn discover_token(&self) -> Option<String> {
+        let host = self.host()?;
+        let envs: &[&str] = match host.as_str() {
+            "github.com" => &["GITHUB_TOKEN", "GH_TOKEN"],
+            _ => &[
+                "GH_ENTERPRISE_TOKEN",
+                "GITHUB_ENTERPRISE_TOKEN",
+                "GITHUB_TOKEN",
+            ],
+        };
+        envs.iter()
+            .find_map(|env| std::env::var(env).ok())
+            .or_else(|| gh_token(&host))
+            .or_else(|| stored_token(&host))
+    }
+
+    // pull request refs, full refs and abbreviated shas aren't understood by both the trees
+    // api and the raw host, they are pinned to their commit
+    async fn resolve(&self, client: &Client, remote: &Remote) -> Result<String> {
+        if let Some(number) = Self::pull_number(&remote.branch) {
+            let url = format!(
+                "{}/repos/{}/{}/pulls/{number}",
+                &self.api, &remote.user, &remote.repo
+            );
+            let pull: PullRequest = self
+                .lookup(client, remote, url)
+                .await?
+                .ok_or_else(|| Error::BranchNotFound(remote.branch.clone()))?;
+            return Ok(pull.head.sha);
+        }
+        let Some(reference) = Self::commit_ref(&remote.branch) else {
+            return Ok(remote.branch.clone());
+        };
+        let url = format!(
+            "{}/repos/{}/{}/commits/{reference}",
+            &self.api, &remote.user, &remote.repo
+        );
+        match self.lookup::<Commit>(client, remote, url).await? {
+            Some(commit) => Ok(commit.sha),
+            // a branch merely named like a sha, such as cafe123, is listed by its name
+            None if !remote.branch.starts_with("refs/") => Ok(remote.branch.clone()),
+            None => Err(Error::BranchNotFound(remote.branch.clone()).into()),
+        }
+    }
+
+    async fn commit(&self, client: &Client, remote: &Remote) -> Result<Option<String>> {
+        let reference = &remote.branch;
+        if reference.len() == 40 && reference.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Ok(Some(reference.clone()));
+        }
+        let url = format!(
+            "{}/repos/{}/{}/commits/{reference}",
+            &self.api, &remote.user, &remote.repo
+        );
+        let commit: Commit = self
+            .lookup(client, remote, url)
+            .await?
+            .ok_or_else(|| Error::BranchNotFound(reference.clone()))?;
+        Ok(Some(commit.sha))
+    }
+
+    async fn changes(
+        &self,
+        client: &Client,
+        remote: &Remote,
+        since: &str,
+    ) -> Result<Option<Vec<Change>>> {
+        let url = format!(
+            "{}/repos/{}/{}/compare/{since}...{}",
+            &self.api, &remote.user, &remote.repo, &remote.branch
+        );
+        // the ref was resolved already, a missing commit is the one compared with
+        let comparison: Comparison = self
+            .lookup(client, remote, url)
+            .await?
+            .ok_or_else(|| Error::BranchNotFound(since.into()))?;
+        // the comparison lists at most 300 files, a longer one is listed whole instead
+        if comparison.files.len() >= Self::MAX_COMPARED {
+            return Ok(None);
+        }
+        let mut changes = vec![];
+        for file in comparison.files {
+            if let Some(previous) = file.previous_filename {
+                changes.push(Change::Removed { path: previous });
+            }
+            changes.push(match file.status.as_str() {
+                "removed" => Change::Removed {
+                    path: file.filename,
+                },
+                _ => Change::Changed {
+                    path: file.filename,
+                    sha: file.sha,
+                },
+            });
+        }
+        Ok(Some(changes))
+    }
+
+    async fn tree(&self, client: &Client, remote: &Remote) -> Result<Vec<Node>> {
+        if self.graphql {
+            return graphql::tree(client, &self.graphql_url(), remote).await;
+        }
+        match self.trees(client, remote).await {
+            Err(err) if Self::unlisted(&err) => {
+                self.contents(client, remote).await.map_err(|_| err)
+            }
+            res => res,
+        }
+    }
+
+    // anonymous downloads from github.com go straight to codeload, sparing the api quota,
+    // the api answers the others with a redirect to it
+    fn archive(&self, client: &Client, remote: &Remote) -> Option<RequestBuilder> {
+        if remote.token.is_none() && self.host().as_deref() == Some("github.com") {
+            let url = format!(
+                "{}/{}/{}/tar.gz/{}",
+                Self::CODELOAD,
+                &remote.user,
+                &remote.repo,
+                &remote.branch
+            );
+            return Some(client.get(url));
+        }
+        let url = format!(
+            "{}/repos/{}/{}/tarball/{}",
+            &self.api, &remote.user, &remote.repo, &remote.branch
+        );
+        Some(self.get(client, remote, url))
+    }
+
+    fn lfs(&self, client: &Client, remote: &Remote) -> Option<RequestBuilder> {
+        let base = format!("https://{}", self.host()?);
+        Some(lfs_batch(client, &base, remote, "x-access-token"))
+    }
+
+    fn raw(&self, client: &Client, remote: &Remote, path: &str) -> RequestBuilder {
+        let url = format!(
+            "{}/{}/{}/{}/{path}",
+            &self.raw, &remote.user, &remote.repo, &remote.branch
+        );
+        self.get(client, remote, url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stub::{Response, Stub};
+
+    fn remote() -> Remote {
+        Remote {
+            user: "levinion".into(),
+            repo: "dotfiles".into(),
+            branch: "main".into(),
+            path: "".into(),
+            token: None,
+        }
+    }
+
+    const CONTENTS: &str = r#"[{"path": "init.lua", "type": "file", "size": 4, "sha": "abc"}]"#;
+
+    #[tokio::test]
+    async fn tells_a_missing_commit_from_a_failed_lookup() {
+        let stub = Stub::start(|req| match req.path.as_str() {
+            "/repos/levinion/dotfiles/commits/v1" => Response::ok(r#"{"sha": "0123abc"}"#),
+            "/repos/levinion/dotfiles/commits/gone" => Response::new(422),
+            "/repos/levinion/dotfiles/commits/beef123" => Response::new(404),
+            _ => Response::new(400),
+        })
+        .await;
+        let github = GitHub::new(&stub.url, &stub.url);
+        let at = |branch: &str| Remote {
+            branch: branch.into(),
+            ..remote()
+        };
+        let commit = github.commit(&Client::new(), &at("v1")).await.unwrap();
+        assert_eq!(commit.as_deref(), Some("0123abc"));
+        let err = github
+            .commit(&Client::new(), &at("gone"))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(Error::BranchNotFound(_))),
+            "{err:?}"
+        );
+        let err = github
+            .commit(&Client::new(), &at("down"))
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<reqwest::Error>().is_some(), "{err:?}");
+        // a branch named like a sha matching no commit is kept, unless the lookup failed
+        let branch = github
+            .resolve(&Client::new(), &at("beef123"))
+            .await
+            .unwrap();
+        assert_eq!(branch, "beef123");
+        assert!(github
+            .resolve(&Client::new(), &at("cafe123"))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn tells_a_missing_base_commit_from_a_rate_limit() {
+        let stub = Stub::start(|req| match req.path.as_str() {
+            "/repos/levinion/dotfiles/compare/gone...main" => Response::new(404),
+            _ => Response::new(403)
+                .header("x-ratelimit-remaining", 0)
+                // reset already, so it isn't waited for
+                .header("x-ratelimit-reset", 0),
+        })
+        .await;
+        let github = GitHub::new(&stub.url, &stub.url);
+        let err = github
+            .changes(&Client::new(), &remote(), "gone")
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::BranchNotFound(sha)) if sha == "gone"));
+        let err = github
+            .changes(&Client::new(), &remote(), "0123abc")
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(Error::RateLimited { .. })),
+            "{err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn lists_an_empty_repo_through_the_contents_api() {
+        let stub = Stub::start(|req| match req.path.contains("/git/trees/") {
+            true => Response::new(409),
+            false => Response::ok(CONTENTS),
+        })
+        .await;
+        let github = GitHub::new(&stub.url, &stub.url);
+        let tree = github.tree(&Client::new(), &remote()).await.unwrap();
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree[0].path, "init.lua");
+    }
+
+    #[tokio::test]
+    async fn passes_other_failures_on() {
+        let stub = Stub::start(|req| match req.path.contains("/git/trees/") {
+            true => Response::new(401),
+            false => Response::ok(CONTENTS),
+        })
+        .await;
+        let github = GitHub::new(&stub.url, &stub.url);
+        let err = github.tree(&Client::new(), &remote()).await.unwrap_err();
+        let status = err
+            .downcast_ref::<reqwest::Error>()
+            .and_then(|err| err.status());
+        assert_eq!(status, Some(StatusCode::UNAUTHORIZED), "{err:?}");
+        assert!(stub
+            .requests()
+            .iter()
+            .all(|req| !req.path.contains("/contents/")));
+    }
+}
@@ -0,0 +1,274 @@
+use anyhow::{anyhow, Result};
+use reqwest::{Client, RequestBuilder, Response, StatusCode, Url};
+
+use super::{api_base, async_trait, base_url, host_of, lfs_batch, Provider};
+use crate::{rate_limit::send, Error, Node, Remote};
+
+#[derive(serde::Deserialize)]
+struct Commit {
+    id: String,
+}
+
+pub struct GitLab {
+    host: String,
+    base: Url,
+}
+
+impl GitLab {
+    const PER_PAGE: &'static str = "100";
+
+    /// Refuses a host that isn't a url, such as `https://gitlab.com` or `gitlab.com`
+    pub fn new(host: &str) -> Result<Self, Error> {
+        let host = base_url(host);
+        let base = api_base(&host, "gitlab host")?;
+        Ok(Self { host, base })
+    }
+
+    // the project is addressed by its url-encoded full path, such as levinion%2Fdotfiles
+    fn url(&self, remote: &Remote, segments: &[&str]) -> Url {
+        let mut url = self.project(remote);
+        // the base was checked to take path segments
+        url.path_segments_mut()
+            .unwrap()
+            .extend(["repository"])
+            .extend(segments);
+        url
+    }
+
+    fn project(&self, remote: &Remote) -> Url {
+        let mut url = self.base.clone();
+        url.path_segments_mut()
+            .unwrap()
+            .extend(["api", "v4", "projects"])
+            .push(&format!("{}/{}", remote.user, remote.repo));
+        url
+    }
+
+    // the entries below `path`, page by page as x-next-page is empty on the last one,
+    // none when there is no such directory
+    async fn list(
+        &self,
+        client: &Client,
+        remote: &Remote,
+        path: &str,
+        recursive: bool,
+    ) -> Result<Option<Vec<Node>>> {
+        let mut tree = vec![];
+        let mut page = String::from("1");
+        while !page.is_empty() {
+            let mut url = self.url(remote, &["tree"]);
+            url.query_pairs_mut()
+                .append_pair("path", path)
+                .append_pair("recursive", &recursive.to_string())
+                .append_pair("ref", &remote.branch)
+                .append_pair("per_page", Self::PER_PAGE)
+                .append_pair("page", &page);
+            let res = send(self.get(client, remote, url)).await?;
+            match res.status() {
+                StatusCode::NOT_FOUND => return Ok(None),
+                status if !status.is_success() => {
+                    return Err(self.refused(client, remote, &res).await)
+                }
+                _ => {}
+            }
+            page = res
+                .headers()
+                .get("x-next-page")
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            let nodes: Vec<Node> = serde_json::from_slice(&res.bytes().await?)?;
+            tree.extend(nodes);
+        }
+        Ok(Some(tree))
+    }
+
+    // why the api refused to answer, a missing project, ref and path all answer 404
+    async fn refused(&self, client: &Client, remote: &Remote, res: &Response) -> anyhow::Error {
+        if let Some(err) = Error::rate_limited(res) {
+            return err.into();
+        }
+        match res.error_for_status_ref() {
+            Err(_) if res.status() == StatusCode::NOT_FOUND => {}
+            Err(err) => return err.into(),
+            Ok(res) => return anyhow!("The api answered {}", res.status()),
+        }
+        self.missing(client, remote).await
+    }
+
+    // which of the project, the ref and the path isn't there, the project and the commit
+    // are looked up to tell them apart
+    async fn missing(&self, client: &Client, remote: &Remote) -> anyhow::Error {
+        let found = |res: Result<Response>| res.is_ok_and(|res| res.status().is_success());
+        let project = self.get(client, remote, self.project(remote));
+        if !found(send(project).await) {
+            return Error::repo_not_found(remote).into();
+        }
+        let commit = self.url(remote, &["commits", &remote.branch]);
+        match found(send(self.get(client, remote, commit)).await) {
+            true => Error::PathNotFound(remote.path.clone()).into(),
+            false => Error::BranchNotFound(remote.branch.clone()).into(),
+        }
+    }
+
+    fn get(&self, client: &Client, remote: &Remote, url: Url) -> RequestBuilder {
+        let req = client.get(url);
+        match &remote.token {
+            Some(token) => req.header("PRIVATE-TOKEN", token),
+            None => req,
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for GitLab {
+    fn token_env(&self) -> Option<&'static str> {
+        Some("GITLAB_TOKEN")
+    }
+
+    fn host(&self) -> Option<String> {
+        host_of(&self.host)
+    }
+
+    async fn commit(&self, client: &Client, remote: &Remote) -> Result<Option<String>> {
+        let url = self.url(remote, &["commits", &remote.branch]);
+        let res = send(self.get(client, remote, url)).await?;
+        if !res.status().is_success() {
+            return Err(match res.status() {
+                StatusCode::NOT_FOUND => Error::BranchNotFound(remote.branch.clone()).into(),
+                _ => self.refused(client, remote, &res).await,
+            });
+        }
+        let commit: Commit = serde_json::from_slice(&res.bytes().await?)?;
+        Ok(Some(commit.id))
+    }
+
+    // only the remote path is listed rather than the whole repo
+    async fn tree(&self, client: &Client, remote: &Remote) -> Result<Vec<Node>> {
+        let path = remote.path.trim_matches('/');
+        if let Some(tree) = self.list(client, remote, path, true).await? {
+            if !tree.is_empty() || path.is_empty() {
+                return Ok(tree);
+            }
+        }
+        if path.is_empty() {
+            return Err(self.missing(client, remote).await);
+        }
+        // a file isn't listed below itself, it is found in its directory
+        let (dir, _) = path.rsplit_once('/').unwrap_or_default();
+        let siblings = self.list(client, remote, dir, false).await?;
+        match siblings
+            .into_iter()
+            .flatten()
+            .find(|node| node.path == path)
+        {
+            Some(node) => Ok(vec![node]),
+            None => Err(self.missing(client, remote).await),
+        }
+    }
+
+    fn archive(&self, client: &Client, remote: &Remote) -> Option<RequestBuilder> {
+        let mut url = self.url(remote, &["archive.tar.gz"]);
+        url.query_pairs_mut().append_pair("sha", &remote.branch);
+        Some(self.get(client, remote, url))
+    }
+
+    fn lfs(&self, client: &Client, remote: &Remote) -> Option<RequestBuilder> {
+        Some(lfs_batch(client, &self.host, remote, "oauth2"))
+    }
+
+    fn raw(&self, client: &Client, remote: &Remote, path: &str) -> RequestBuilder {
+        let mut url = self.url(remote, &["files", path, "raw"]);
+        url.query_pairs_mut().append_pair("ref", &remote.branch);
+        self.get(client, remote, url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stub::{Response, Stub};
+
+    const PROJECT: &str = "/api/v4/projects/levinion%2Fdotfiles";
+
+    fn remote(path: &str) -> Remote {
+        Remote {
+            user: "levinion".into(),
+            repo: "dotfiles".into(),
+            branch: "main".into(),
+            path: path.into(),
+            token: None,
+        }
+    }
+
+    fn node(path: &str, kind: &str) -> String {
+        format!(r#"{{"path": "{path}", "type": "{kind}", "mode": "100644", "id": "abc"}}"#)
+    }
+
+    #[tokio::test]
+    async fn lists_the_remote_path_page_by_page() {
+        let stub = Stub::start(|req| {
+            let query = req
+                .path
+                .split_once('?')
+                .map(|(_, query)| query)
+                .unwrap_or_default();
+            match query.contains("page=2") {
+                false => {
+                    Response::ok(format!("[{}]", node("nvim/lua", "tree"))).header("x-next-page", 2)
+                }
+                true => Response::ok(format!("[{}]", node("nvim/lua/init.lua", "blob"))),
+            }
+        })
+        .await;
+        let gitlab = GitLab::new(&stub.url).unwrap();
+        let tree = gitlab.tree(&Client::new(), &remote("nvim")).await.unwrap();
+        let paths: Vec<_> = tree.iter().map(|node| node.path.as_str()).collect();
+        assert_eq!(paths, ["nvim/lua", "nvim/lua/init.lua"]);
+        let requests = stub.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests
+            .iter()
+            .all(|req| req.path.contains("path=nvim&recursive=true")));
+    }
+
+    #[tokio::test]
+    async fn finds_a_file_in_its_directory() {
+        let stub = Stub::start(|req| match req.path.contains("path=nvim%2Finit.lua") {
+            true => Response::ok("[]"),
+            false => Response::ok(format!("[{}]", node("nvim/init.lua", "blob"))),
+        })
+        .await;
+        let gitlab = GitLab::new(&stub.url).unwrap();
+        let remote = remote("nvim/init.lua");
+        let tree = gitlab.tree(&Client::new(), &remote).await.unwrap();
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree[0].path, "nvim/init.lua");
+    }
+
+    #[tokio::test]
+    async fn tells_what_is_missing() {
+        let stub = Stub::start(|req| match req.path.as_str() {
+            PROJECT => Response::ok("{}"),
+            _ => Response::new(404),
+        })
+        .await;
+        let gitlab = GitLab::new(&stub.url).unwrap();
+        let err = gitlab.tree(&Client::new(), &remote("")).await.unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(Error::BranchNotFound(_))),
+            "{err:?}"
+        );
+        let stub = Stub::start(|_| Response::new(404)).await;
+        let gitlab = GitLab::new(&stub.url).unwrap();
+        let err = gitlab.tree(&Client::new(), &remote("")).await.unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(Error::RepoNotFound { .. })),
+            "{err:?}"
+        );
+        let stub = Stub::start(|_| Response::new(401)).await;
+        let gitlab = GitLab::new(&stub.url).unwrap();
+        let err = gitlab.tree(&Client::new(), &remote("")).await.unwrap_err();
+        assert!(err.downcast_ref::<reqwest::Error>().is_some(), "{err:?}");
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use reqwest::Client;
+use serde_json::{json, Map, Value};
+
+use crate::{rate_limit::send, Node, Remote};
+
+#[derive(serde::Deserialize, Debug)]
+struct Blob {
+    #[serde(rename = "byteSize")]
+    byte_size: Option<u64>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Entry {
+    path: String,
+    #[serde(rename = "type")]
+    kind: String,
+    oid: String,
+    mode: u32,
+    object: Option<Blob>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Tree {
+    #[serde(default)]
+    entries: Vec<Entry>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Data {
+    repository: Option<HashMap<String, Option<Tree>>>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Error {
+    message: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Response {
+    data: Option<Data>,
+    #[serde(default)]
+    errors: Vec<Error>,
+}
+
+// directories fetched by a single query, each one an aliased field
+const BATCH: usize = 50;
+
+const FRAGMENT: &str = "fragment entries on Tree { entries { path type oid mode \
+    object { ... on Blob { byteSize } } } }";
+
+// walks the tree level by level, every level of the directories related to the remote path
+// takes one query instead of a request per directory
+pub(super) async fn tree(client: &Client, endpoint: &str, remote: &Remote) -> Result<Vec<Node>> {
+    let token = remote
+        .token
+        .as_ref()
+        .ok_or_else(|| anyhow!("The GraphQL api needs a token"))?;
+    let mut tree = vec![];
+    let mut dirs = vec![String::new()];
+    while !dirs.is_empty() {
+        let batch: Vec<_> = dirs.drain(..dirs.len().min(BATCH)).collect();
+        let fields: String = (0..batch.len())
+            .map(|i| format!("d{i}: object(expression: $e{i}) {{ ...entries }} "))
+            .collect();
+        let params: String = (0..batch.len())
+            .map(|i| format!(", $e{i}: String!"))
+            .collect();
+        let query = format!(
+            "query($owner: String!, $name: String!{params}) \
+            {{ repository(owner: $owner, name: $name) {{ {fields}}} }} {FRAGMENT}"
+        );
+        let mut variables = Map::new();
+        variables.insert("owner".into(), json!(remote.user));
+        variables.insert("name".into(), json!(remote.repo));
+        for (i, dir) in batch.iter().enumerate() {
+            variables.insert(format!("e{i}"), json!(format!("{}:{dir}", remote.branch)));
+        }
+        let body = json!({ "query": query, "variables": Value::Object(variables) });
+        let req = client
+            .post(endpoint)
+            .bearer_auth(token)
+            .body(body.to_string());
+        let res = send(req).await?.bytes().await?;
+        let res: Response =
+            serde_json::from_slice(&res).map_err(|_| crate::Error::repo_not_found(remote))?;
+        if let Some(err) = res.errors.first() {
+            return Err(anyhow!("{}", err.message));
+        }
+        let repository = res
+            .data
+            .and_then(|data| data.repository)
+            .ok_or_else(|| crate::Error::repo_not_found(remote))?;
+        for entry in repository
+            .into_values()
+            .flatten()
+            .flat_map(|tree| tree.entries)
+        {
+            let related =
+                entry.path.starts_with(&remote.path) || remote.path.starts_with(&entry.path);
+            if entry.kind == "tree" && related {
+                dirs.push(entry.path.clone());
+            }
+            tree.push(Node {
+                path: entry.path,
+                kind: entry.kind,
+                size: entry.object.and_then(|blob| blob.byte_size),
+                sha: Some(entry.oid),
+                mode: Some(format!("{:o}", entry.mode)),
+            });
+        }
+    }
+    Ok(tree)
+}
@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use reqwest::{Client, RequestBuilder};
+
+use super::{async_trait, Provider};
+use crate::{Change, Node, Remote};
+
+/// Lists the tree through `inner` but fetches file content from a mirror host
+#[derive(Clone)]
+pub struct Mirror {
+    inner: Arc<dyn Provider>,
+    template: String,
+}
+
+impl Mirror {
+    /// jsDelivr's cdn for github repos, unauthenticated and cached globally
+    pub const JSDELIVR: &'static str = "https://cdn.jsdelivr.net/gh/{user}/{repo}@{branch}/{path}";
+
+    /// `template` is the url of a file, such as
+    /// `https://mirror.example.com/{user}/{repo}/{branch}/{path}`
+    pub fn new(inner: Arc<dyn Provider>, template: &str) -> Self {
+        Self {
+            inner,
+            template: template.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for Mirror {
+    fn token_env(&self) -> Option<&'static str> {
+        self.inner.token_env()
+    }
+
+    fn host(&self) -> Option<String> {
+        self.inner.host()
+    }
+
+    fn discover_token(&self) -> Option<String> {
+        self.inner.discover_token()
+    }
+
+    async fn resolve(&self, client: &Client, remote: &Remote) -> Result<String> {
+        self.inner.resolve(client, remote).await
+    }
+
+    async fn commit(&self, client: &Client, remote: &Remote) -> Result<Option<String>> {
+        self.inner.commit(client, remote).await
+    }
+
+    async fn changes(
+        &self,
+        client: &Client,
+        remote: &Remote,
+        since: &str,
+    ) -> Result<Option<Vec<Change>>> {
+        self.inner.changes(client, remote, since).await
+    }
+
+    async fn tree(&self, client: &Client, remote: &Remote) -> Result<Vec<Node>> {
+        self.inner.tree(client, remote).await
+    }
+
+    fn archive(&self, client: &Client, remote: &Remote) -> Option<RequestBuilder> {
+        self.inner.archive(client, remote)
+    }
+
+    fn lfs(&self, client: &Client, remote: &Remote) -> Option<RequestBuilder> {
+        self.inner.lfs(client, remote)
+    }
+
+    // the token belongs to the forge and is never sent to the mirror
+    fn raw(&self, client: &Client, remote: &Remote, path: &str) -> RequestBuilder {
+        let url = self
+            .template
+            .replace("{user}", &remote.user)
+            .replace("{repo}", &remote.repo)
+            .replace("{branch}", &remote.branch)
+            .replace("{path}", path);
+        client.get(url)
+    }
+}
@@ -0,0 +1,152 @@
+use anyhow::Result;
+
+use crate::{auth::stored_token, Change, Node, Remote};
+
+mod azure;
+#[cfg(feature = "codecommit")]
+mod codecommit;
+mod gist;
+mod gitea;
+mod github;
+mod gitlab;
+mod graphql;
+mod mirror;
+mod release;
+mod sourcehut;
+mod wiki;
+
+pub use async_trait::async_trait;
+pub use azure::AzureDevOps;
+pub use bytes::Bytes;
+#[cfg(feature = "codecommit")]
+pub use codecommit::CodeCommit;
+pub use gist::Gist;
+pub use gitea::Gitea;
+pub use github::GitHub;
+pub use gitlab::GitLab;
+pub use mirror::Mirror;
+pub use release::Release;
+pub use reqwest::{Client, RequestBuilder};
+pub use sourcehut::SourceHut;
+pub use wiki::Wiki;
+
+/// A git forge the files are downloaded from, implement it to support other forges
+#[async_trait]
+pub trait Provider: Send + Sync {
+    /// Environment variable the token is read from when none is given
+    fn token_env(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Host the credentials are looked up for in `~/.netrc` and the keyring
+    fn host(&self) -> Option<String> {
+        None
+    }
+
+    /// Token discovered from the environment when none is given
+    fn discover_token(&self) -> Option<String> {
+        self.token_env()
+            .and_then(|env| std::env::var(env).ok())
+            .or_else(|| stored_token(&self.host()?))
+    }
+
+    /// Resolves the configured branch into the ref the tree and files are fetched at,
+    /// full refs such as `refs/tags/v1.2.3` are shortened by default
+    async fn resolve(&self, _client: &Client, remote: &Remote) -> Result<String> {
+        let branch = &remote.branch;
+        let name = ["refs/heads/", "refs/tags/"]
+            .iter()
+            .find_map(|prefix| branch.strip_prefix(prefix))
+            .unwrap_or(branch);
+        Ok(name.to_string())
+    }
+
+    /// The sha of the commit the resolved ref points to, when the forge tells it, such as
+    /// for a [`Lockfile`](crate::Lockfile)
+    async fn commit(&self, _client: &Client, _remote: &Remote) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// The files changed between the commit `since` and the resolved ref, none when the
+    /// forge can't tell them, then the whole tree is listed instead
+    async fn changes(
+        &self,
+        _client: &Client,
+        _remote: &Remote,
+        _since: &str,
+    ) -> Result<Option<Vec<Change>>> {
+        Ok(None)
+    }
+
+    /// Lists every node of the remote tree
+    async fn tree(&self, client: &Client, remote: &Remote) -> Result<Vec<Node>>;
+
+    /// Request fetching the raw content of a single file
+    fn raw(&self, client: &Client, remote: &Remote, path: &str) -> RequestBuilder;
+
+    /// Request fetching the whole tree at the ref as a tar.gz, the first path component of
+    /// every entry is the archive's own top directory and dropped
+    fn archive(&self, _client: &Client, _remote: &Remote) -> Option<RequestBuilder> {
+        None
+    }
+
+    /// Request asking the git lfs batch api where the objects of the repo are downloaded
+    /// from, the body is added to it, none when the forge serves no lfs
+    fn lfs(&self, _client: &Client, _remote: &Remote) -> Option<RequestBuilder> {
+        None
+    }
+
+    /// Whether the body answered to [`Provider::raw`] is buffered whole and passed through
+    /// [`Provider::decode`], otherwise it is streamed to disk as is
+    fn buffered(&self) -> bool {
+        false
+    }
+
+    /// Turns the buffered body answered to [`Provider::raw`] into the file content, parts of
+    /// it are best handed on with [`Bytes::slice`], which shares the body instead of copying
+    fn decode(&self, body: Bytes) -> Result<Bytes> {
+        Ok(body)
+    }
+}
+
+// hosts may be given without a scheme, such as codeberg.org
+pub(crate) fn base_url(host: &str) -> String {
+    let host = host.trim_end_matches('/');
+    match host.contains("://") {
+        true => host.to_string(),
+        false => format!("https://{host}"),
+    }
+}
+
+// the url the api paths are appended to, refused as a setting when it can't take them
+pub(crate) fn api_base(host: &str, field: &'static str) -> Result<reqwest::Url, crate::Error> {
+    let invalid = |reason: String| crate::Error::InvalidSetting { field, reason };
+    let url = reqwest::Url::parse(host).map_err(|err| invalid(err.to_string()))?;
+    match url.cannot_be_a_base() {
+        true => Err(invalid(format!("{host} is no http url"))),
+        false => Ok(url),
+    }
+}
+
+// the batch api git lfs serves beside the repo, the token goes in as the password of
+// `username`, as git sends it
+pub(crate) fn lfs_batch(
+    client: &Client,
+    base: &str,
+    remote: &Remote,
+    username: &str,
+) -> RequestBuilder {
+    let url = format!(
+        "{base}/{}/{}.git/info/lfs/objects/batch",
+        remote.user, remote.repo
+    );
+    let req = client.post(url);
+    match &remote.token {
+        Some(token) => req.basic_auth(username, Some(token)),
+        None => req,
+    }
+}
+
+pub(crate) fn host_of(url: &str) -> Option<String> {
+    Some(reqwest::Url::parse(url).ok()?.host_str()?.to_string())
+}
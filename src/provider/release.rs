@@ -0,0 +1,122 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::{anyhow, Result};
+use globset::{Glob, GlobSetBuilder};
+use reqwest::{Client, RequestBuilder};
+
+use super::{async_trait, Provider};
+use crate::{rate_limit::send, Node, Remote};
+
+#[derive(serde::Deserialize, Debug)]
+struct Asset {
+    name: String,
+    url: String,
+    size: u64,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Assets {
+    assets: Vec<Asset>,
+}
+
+/// The assets of a GitHub release, every asset sits at the root
+pub struct Release {
+    tag: Option<String>,
+    patterns: Vec<String>,
+    // assets are fetched from the api urls handed out by the listing
+    urls: Mutex<HashMap<String, String>>,
+}
+
+impl Release {
+    const API: &'static str = "https://api.github.com";
+
+    /// The latest release of the repo
+    pub fn latest() -> Self {
+        Self {
+            tag: None,
+            patterns: vec![],
+            urls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The release tagged `tag`, such as v1.2.3
+    pub fn tag(tag: &str) -> Self {
+        Self {
+            tag: Some(tag.into()),
+            ..Self::latest()
+        }
+    }
+
+    /// Download only the assets whose name matches the glob, such as `*linux*`
+    pub fn assets(mut self, pattern: &str) -> Self {
+        self.patterns.push(pattern.into());
+        self
+    }
+
+    fn get(&self, client: &Client, remote: &Remote, url: String) -> RequestBuilder {
+        let req = client.get(url);
+        match &remote.token {
+            Some(token) => req.bearer_auth(token),
+            None => req,
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for Release {
+    fn token_env(&self) -> Option<&'static str> {
+        Some("GITHUB_TOKEN")
+    }
+
+    fn host(&self) -> Option<String> {
+        Some("github.com".into())
+    }
+
+    async fn tree(&self, client: &Client, remote: &Remote) -> Result<Vec<Node>> {
+        let release = match &self.tag {
+            Some(tag) => format!("tags/{tag}"),
+            None => "latest".into(),
+        };
+        let url = format!(
+            "{}/repos/{}/{}/releases/{release}",
+            Self::API,
+            remote.user,
+            remote.repo
+        );
+        let res = send(self.get(client, remote, url)).await?.bytes().await?;
+        let release: Assets = serde_json::from_slice(&res)
+            .map_err(|_| anyhow!("Are you sure the release really exists?"))?;
+
+        let mut globs = GlobSetBuilder::new();
+        for pattern in &self.patterns {
+            globs.add(Glob::new(pattern)?);
+        }
+        let globs = globs.build()?;
+        let mut urls = self.urls.lock().unwrap();
+        let tree = release
+            .assets
+            .into_iter()
+            .filter(|asset| self.patterns.is_empty() || globs.is_match(&asset.name))
+            .map(|asset| {
+                let node = Node::blob(&asset.name).size(asset.size);
+                urls.insert(asset.name, asset.url);
+                node
+            })
+            .collect();
+        Ok(tree)
+    }
+
+    // the api url serves the asset itself when asking for octet-stream, also for private repos
+    fn raw(&self, client: &Client, remote: &Remote, path: &str) -> RequestBuilder {
+        let url = self.urls.lock().unwrap().get(path).cloned();
+        let url = url.unwrap_or_else(|| {
+            let tag = self.tag.as_deref().unwrap_or("latest");
+            format!(
+                "https://github.com/{}/{}/releases/download/{tag}/{path}",
+                remote.user, remote.repo
+            )
+        });
+        self.get(client, remote, url)
+            .header("Accept", "application/octet-stream")
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::Result;
+use reqwest::{Client, RequestBuilder};
+
+use super::{async_trait, base_url, host_of, Provider};
+use crate::{Error, Node, Remote};
+
+#[derive(serde::Deserialize, Debug)]
+struct Entry {
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    id: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Tree {
+    entries: Vec<Entry>,
+}
+
+/// git.sr.ht and self-hosted sourcehut instances
+pub struct SourceHut {
+    host: String,
+}
+
+impl Default for SourceHut {
+    fn default() -> Self {
+        Self::new("https://git.sr.ht")
+    }
+}
+
+impl SourceHut {
+    pub fn new(host: &str) -> Self {
+        Self {
+            host: base_url(host),
+        }
+    }
+
+    // sourcehut users are addressed with a leading tilde, such as ~levinion
+    fn owner(remote: &Remote) -> String {
+        format!("~{}", remote.user.trim_start_matches('~'))
+    }
+
+    fn get(&self, client: &Client, remote: &Remote, url: String) -> RequestBuilder {
+        let req = client.get(url);
+        match &remote.token {
+            Some(token) => req.header("Authorization", format!("token {token}")),
+            None => req,
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for SourceHut {
+    fn token_env(&self) -> Option<&'static str> {
+        Some("SRHT_TOKEN")
+    }
+
+    fn host(&self) -> Option<String> {
+        host_of(&self.host)
+    }
+
+    // the tree endpoint isn't recursive, only directories leading to or below the
+    // remote path are walked
+    async fn tree(&self, client: &Client, remote: &Remote) -> Result<Vec<Node>> {
+        let mut tree = vec![];
+        let mut dirs = vec![String::new()];
+        while let Some(dir) = dirs.pop() {
+            let url = format!(
+                "{}/api/{}/repos/{}/tree/{}/{dir}",
+                self.host,
+                Self::owner(remote),
+                remote.repo,
+                remote.branch
+            );
+            let res = self.get(client, remote, url).send().await?.bytes().await?;
+            let entries: Tree =
+                serde_json::from_slice(&res).map_err(|_| Error::repo_not_found(remote))?;
+            for entry in entries.entries {
+                let path = match dir.is_empty() {
+                    true => entry.name,
+                    false => format!("{dir}/{}", entry.name),
+                };
+                let related = path.starts_with(&remote.path) || remote.path.starts_with(&path);
+                if entry.kind == "tree" && related {
+                    dirs.push(path.clone());
+                }
+                tree.push(Node {
+                    path,
+                    kind: entry.kind,
+                    size: None,
+                    sha: entry.id,
+                    mode: None,
+                });
+            }
+        }
+        Ok(tree)
+    }
+
+    fn raw(&self, client: &Client, remote: &Remote, path: &str) -> RequestBuilder {
+        let url = format!(
+            "{}/{}/{}/blob/{}/{path}",
+            self.host,
+            Self::owner(remote),
+            remote.repo,
+            remote.branch
+        );
+        self.get(client, remote, url)
+    }
+}
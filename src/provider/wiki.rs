@@ -0,0 +1,83 @@
+use anyhow::Result;
+use reqwest::{Client, RequestBuilder};
+
+use super::{async_trait, GitHub, Provider};
+use crate::{Node, Remote};
+
+/// The wiki of a GitHub repo, every page is downloaded as the file it is kept in
+#[derive(Default)]
+pub struct Wiki {
+    github: GitHub,
+}
+
+impl Wiki {
+    fn get(&self, client: &Client, remote: &Remote, url: String) -> RequestBuilder {
+        let req = client.get(url);
+        match &remote.token {
+            Some(token) => req.bearer_auth(token),
+            None => req,
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for Wiki {
+    fn token_env(&self) -> Option<&'static str> {
+        Some("GITHUB_TOKEN")
+    }
+
+    fn host(&self) -> Option<String> {
+        Some("github.com".into())
+    }
+
+    // the wiki is a git repo of its own beside the repo, its pages are listed from its
+    // tree with the extension of their markup, such as .md, .rst or .asciidoc
+    async fn tree(&self, client: &Client, remote: &Remote) -> Result<Vec<Node>> {
+        let mut wiki = remote.clone();
+        wiki.repo = format!("{}.wiki", remote.repo);
+        self.github
+            .tree(client, &wiki)
+            .await
+            .map_err(|err| err.context("Are you sure the wiki really exists?"))
+    }
+
+    fn raw(&self, client: &Client, remote: &Remote, path: &str) -> RequestBuilder {
+        let url = format!(
+            "https://raw.githubusercontent.com/wiki/{}/{}/{path}",
+            remote.user, remote.repo
+        );
+        self.get(client, remote, url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stub::{Response, Stub};
+
+    #[tokio::test]
+    async fn lists_the_tree_of_the_wiki_repo() {
+        let stub = Stub::start(|req| match req.path.as_str() {
+            "/repos/levinion/dotfiles.wiki/git/trees/master?recursive=1" => Response::ok(
+                r#"{"tree": [{"path": "Home.md", "type": "blob", "sha": "a"},
+                             {"path": "Setup.rst", "type": "blob", "sha": "b"}],
+                    "truncated": false}"#,
+            ),
+            _ => Response::new(404),
+        })
+        .await;
+        let wiki = Wiki {
+            github: GitHub::new(&stub.url, &stub.url),
+        };
+        let remote = Remote {
+            user: "levinion".into(),
+            repo: "dotfiles".into(),
+            branch: "master".into(),
+            path: "".into(),
+            token: None,
+        };
+        let tree = wiki.tree(&Client::new(), &remote).await.unwrap();
+        let paths: Vec<_> = tree.iter().map(|node| node.path.as_str()).collect();
+        assert_eq!(paths, ["Home.md", "Setup.rst"]);
+    }
+}
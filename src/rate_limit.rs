@@ -0,0 +1,157 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use reqwest::{
+    header::{HeaderMap, RETRY_AFTER},
+    RequestBuilder, Response,
+};
+
+use crate::{retry::Retry, Error};
+
+/// The request quota left, as reported by the `X-RateLimit-*` response headers
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    pub limit: Option<u64>,
+    pub remaining: u64,
+    /// When the quota is refilled
+    pub reset: SystemTime,
+}
+
+impl RateLimit {
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name: &str| headers.get(name)?.to_str().ok()?.parse::<u64>().ok();
+        Some(Self {
+            limit: header("x-ratelimit-limit"),
+            remaining: header("x-ratelimit-remaining")?,
+            reset: UNIX_EPOCH + Duration::from_secs(header("x-ratelimit-reset")?),
+        })
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining == 0
+    }
+
+    // how long requests have to wait for the quota, none while some is left
+    pub(crate) fn wait(&self) -> Option<Duration> {
+        match self.is_exhausted() {
+            true => self.reset.duration_since(SystemTime::now()).ok(),
+            false => None,
+        }
+    }
+}
+
+// how often a rate limited request is sent again, a server that keeps refusing it fails it
+// rather than stalling the download
+pub(crate) const ATTEMPTS: usize = 5;
+
+// secondary rate limits answer how many seconds to back off for
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let secs = headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(secs))
+}
+
+// how long a rate limited response asks to wait before the request is sent again
+pub(crate) fn backoff(res: &Response) -> Option<Duration> {
+    if !matches!(res.status().as_u16(), 403 | 429) {
+        return None;
+    }
+    retry_after(res.headers())
+        .or_else(|| RateLimit::from_headers(res.headers()).and_then(|limit| limit.wait()))
+}
+
+// sends an api request, waiting out secondary rate limits and an exhausted quota
+// instead of handing the error body on, timeouts, connection resets and server errors
+// are retried with the backoff of the files
+pub(crate) async fn send(mut req: RequestBuilder) -> Result<Response> {
+    let retries = Retry::default();
+    let (mut attempt, mut failures) = (0, 0);
+    loop {
+        let retry = req.try_clone();
+        let sent = req.send().await.map_err(anyhow::Error::from);
+        let transient = match &sent {
+            Ok(res) => res.status().is_server_error(),
+            Err(err) => Retry::is_transient(err),
+        };
+        let retry = match retry {
+            Some(retry) if transient && failures + 1 < retries.attempts => {
+                tokio::time::sleep(retries.delay(failures)).await;
+                failures += 1;
+                req = retry;
+                continue;
+            }
+            retry => retry,
+        };
+        let res = sent?;
+        match (retry, backoff(&res)) {
+            (Some(_), Some(wait)) if attempt + 1 >= ATTEMPTS => {
+                let quota = RateLimit::from_headers(res.headers());
+                return Err(Error::still_limited(quota, Some(wait)).into());
+            }
+            (Some(retry), Some(wait)) => {
+                tokio::time::sleep(wait).await;
+                attempt += 1;
+                req = retry;
+            }
+            _ => return Ok(res),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::stub::{Response, Stub};
+
+    #[tokio::test]
+    async fn gives_up_on_a_server_backing_off_forever() {
+        let stub = Stub::start(|_| Response::new(429).header("retry-after", 0)).await;
+        let err = send(reqwest::Client::new().get(&stub.url))
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<Error>();
+        assert!(matches!(err, Some(Error::RateLimited { quota: None, .. })));
+        assert_eq!(stub.requests().len(), ATTEMPTS);
+    }
+
+    #[tokio::test]
+    async fn waits_out_a_back_off() {
+        let sent = AtomicUsize::new(0);
+        let stub = Stub::start(move |_| match sent.fetch_add(1, Ordering::Relaxed) {
+            0 => Response::new(403).header("retry-after", 0),
+            _ => Response::ok("done"),
+        })
+        .await;
+        let res = send(reqwest::Client::new().get(&stub.url)).await.unwrap();
+        assert_eq!(res.text().await.unwrap(), "done");
+        assert_eq!(stub.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn retries_a_server_error() {
+        let sent = AtomicUsize::new(0);
+        let stub = Stub::start(move |_| match sent.fetch_add(1, Ordering::Relaxed) {
+            0 => Response::new(502),
+            _ => Response::ok("done"),
+        })
+        .await;
+        let res = send(reqwest::Client::new().get(&stub.url)).await.unwrap();
+        assert_eq!(res.text().await.unwrap(), "done");
+        assert_eq!(stub.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn hands_on_a_server_error_that_persists() {
+        let stub = Stub::start(|_| Response::new(503)).await;
+        let res = send(reqwest::Client::new().get(&stub.url)).await.unwrap();
+        assert_eq!(res.status(), 503);
+        assert_eq!(stub.requests().len(), Retry::default().attempts);
+    }
+}
@@ -0,0 +1,47 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How often and how patiently a file is retried on transient failures
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Retry {
+    pub(crate) attempts: usize,
+    pub(crate) base: Duration,
+    pub(crate) max: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            base: Duration::from_millis(500),
+            max: Duration::from_secs(10),
+        }
+    }
+}
+
+impl Retry {
+    // exponential backoff with jitter, between half and the whole of base * 2^attempt
+    pub(crate) fn delay(&self, attempt: usize) -> Duration {
+        let delay = self
+            .base
+            .saturating_mul(2u32.saturating_pow(attempt as u32))
+            .min(self.max);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos();
+        let half = delay / 2;
+        half + half.mul_f64(nanos as f64 / 1e9)
+    }
+
+    // timeouts, connection resets and 5xx responses are worth another attempt
+    pub(crate) fn is_transient(err: &anyhow::Error) -> bool {
+        if err.is::<tokio::time::error::Elapsed>() {
+            return true;
+        }
+        let Some(err) = err.downcast_ref::<reqwest::Error>() else {
+            return false;
+        };
+        let server_error = err.status().is_some_and(|status| status.is_server_error());
+        err.is_timeout() || err.is_connect() || err.is_request() || err.is_body() || server_error
+    }
+}
@@ -0,0 +1,139 @@
+use std::path::{Component, Path};
+
+// a rule rewriting the path a file is written to below the local path, the rules are
+// applied in the order they are given
+#[derive(Clone, Debug)]
+pub(crate) enum Rewrite {
+    StripPrefix(String),
+    AddPrefix(String),
+    Rename { from: String, to: String },
+}
+
+impl Rewrite {
+    // the rule with its paths as / separated components, none for one climbing out of the
+    // local path
+    pub(crate) fn normalized(&self) -> Option<Self> {
+        Some(match self {
+            Self::StripPrefix(prefix) => Self::StripPrefix(normalize(prefix)?),
+            Self::AddPrefix(prefix) => Self::AddPrefix(normalize(prefix)?),
+            Self::Rename { from, to } => Self::Rename {
+                from: normalize(from)?,
+                to: normalize(to)?,
+            },
+        })
+    }
+
+    pub(crate) fn apply(&self, path: String) -> String {
+        match self {
+            // a file can't be stripped of its whole path
+            Self::StripPrefix(prefix) => match below(&path, prefix) {
+                Some(rest) if !rest.is_empty() => rest.into(),
+                _ => path,
+            },
+            Self::AddPrefix(prefix) => join(prefix, &path),
+            Self::Rename { from, to } => match below(&path, from) {
+                Some(rest) => join(to, rest),
+                None => path,
+            },
+        }
+    }
+}
+
+fn normalize(path: &str) -> Option<String> {
+    let mut parts = vec![];
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy()),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(parts.join("/"))
+}
+
+// the rest of `path` below `prefix`, matched by whole components, such as init.lua of
+// lua/init.lua for lua
+fn below<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    if prefix.is_empty() {
+        return Some(path);
+    }
+    match path.strip_prefix(prefix)? {
+        "" => Some(""),
+        rest => rest.strip_prefix('/'),
+    }
+}
+
+fn join(prefix: &str, path: &str) -> String {
+    match (prefix.is_empty(), path.is_empty()) {
+        (true, _) => path.into(),
+        (_, true) => prefix.into(),
+        _ => format!("{prefix}/{path}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(rule: Rewrite, path: &str) -> String {
+        rule.normalized().unwrap().apply(path.into())
+    }
+
+    #[test]
+    fn strips_a_prefix_of_whole_components() {
+        let strip = || Rewrite::StripPrefix("lua/".into());
+        assert_eq!(apply(strip(), "lua/init.lua"), "init.lua");
+        assert_eq!(apply(strip(), "lua/plugins/a.lua"), "plugins/a.lua");
+        assert_eq!(apply(strip(), "luarocks/a.lua"), "luarocks/a.lua");
+        // a file can't be stripped of its whole path
+        assert_eq!(apply(strip(), "lua"), "lua");
+    }
+
+    #[test]
+    fn adds_a_prefix() {
+        assert_eq!(
+            apply(Rewrite::AddPrefix("./config/nvim".into()), "init.lua"),
+            "config/nvim/init.lua"
+        );
+        assert_eq!(apply(Rewrite::AddPrefix("".into()), "init.lua"), "init.lua");
+    }
+
+    #[test]
+    fn renames_a_file_or_directory() {
+        let rename = || Rewrite::Rename {
+            from: "lua".into(),
+            to: "src/lua".into(),
+        };
+        assert_eq!(apply(rename(), "lua"), "src/lua");
+        assert_eq!(apply(rename(), "lua/init.lua"), "src/lua/init.lua");
+        assert_eq!(apply(rename(), "lua.lua"), "lua.lua");
+    }
+
+    #[test]
+    fn applies_rules_in_order() {
+        let rules = [
+            Rewrite::StripPrefix("src".into()),
+            Rewrite::Rename {
+                from: "main.rs".into(),
+                to: "lib.rs".into(),
+            },
+        ];
+        let path = rules
+            .iter()
+            .fold(String::from("src/main.rs"), |path, rule| rule.apply(path));
+        assert_eq!(path, "lib.rs");
+    }
+
+    #[test]
+    fn refuses_rules_climbing_out() {
+        assert!(Rewrite::AddPrefix("../outside".into())
+            .normalized()
+            .is_none());
+        assert!(Rewrite::StripPrefix("/etc".into()).normalized().is_none());
+        let rename = Rewrite::Rename {
+            from: "a".into(),
+            to: "a/../../b".into(),
+        };
+        assert!(rename.normalized().is_none());
+    }
+}
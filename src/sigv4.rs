@@ -0,0 +1,290 @@
+// aws signature version 4, signed here rather than through the aws sdk, which would bring
+// its own async runtime setup, http stack and credential chain into the tree for the two
+// services used, CodeCommit's GetFolder and GetFile and S3's PutObject; the signing is
+// checked against the vectors of the aws test suite below
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+pub(crate) struct Credentials {
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl Credentials {
+    // from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN`
+    pub(crate) fn from_env() -> Option<Self> {
+        Some(Self {
+            access_key: std::env::var("AWS_ACCESS_KEY_ID").ok().unwrap_or_default(),
+            secret_key: std::env::var("AWS_SECRET_ACCESS_KEY")
+                .ok()
+                .unwrap_or_default(),
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        })
+        .filter(|c| !c.access_key.is_empty() && !c.secret_key.is_empty())
+    }
+
+    pub(crate) fn new(access_key: &str, secret_key: &str, session_token: Option<&str>) -> Self {
+        Self {
+            access_key: access_key.into(),
+            secret_key: secret_key.into(),
+            session_token: session_token.map(Into::into),
+        }
+    }
+
+    // signs a request to `service` with sigv4, `headers` are the lowercase ones sent besides
+    // the host, and the path may end in a query of encoded pairs, answers the headers to add
+    // to them
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn sign(
+        &self,
+        region: &str,
+        service: &str,
+        method: &str,
+        host: &str,
+        path: &str,
+        headers: Vec<(&'static str, String)>,
+        payload_hash: &str,
+    ) -> Vec<(&'static str, String)> {
+        let request = (method, host, path);
+        self.sign_at(
+            SystemTime::now(),
+            region,
+            service,
+            request,
+            headers,
+            payload_hash,
+        )
+    }
+
+    // the signature as of `now`, the request being its method, host and path
+    fn sign_at(
+        &self,
+        now: SystemTime,
+        region: &str,
+        service: &str,
+        (method, host, path): (&str, &str, &str),
+        mut headers: Vec<(&'static str, String)>,
+        payload_hash: &str,
+    ) -> Vec<(&'static str, String)> {
+        let (date, time) = amz_date(now);
+        let mut added = vec![("x-amz-date", time.clone())];
+        if let Some(token) = &self.session_token {
+            added.push(("x-amz-security-token", token.clone()));
+        }
+        headers.push(("host", host.into()));
+        headers.extend(added.iter().cloned());
+        headers.sort_by_key(|(k, _)| *k);
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+        // the pairs sorted, a bare key such as ?uploads taken as having an empty value
+        let mut pairs: Vec<_> = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+            .collect();
+        pairs.sort();
+        let query = pairs
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect::<Vec<_>>()
+            .join("&");
+        let signed = headers
+            .iter()
+            .map(|(k, _)| *k)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical = format!(
+            "{method}\n{path}\n{query}\n{}\n{signed}\n{}",
+            headers
+                .iter()
+                .map(|(k, v)| format!("{k}:{v}\n"))
+                .collect::<String>(),
+            payload_hash
+        );
+        let scope = format!("{date}/{region}/{service}/aws4_request");
+        let to_sign = format!(
+            "AWS4-HMAC-SHA256\n{time}\n{scope}\n{}",
+            hex(&Sha256::digest(canonical.as_bytes()))
+        );
+        let key = [region, service, "aws4_request"].iter().fold(
+            hmac(format!("AWS4{}", self.secret_key).as_bytes(), &date),
+            |key, part| hmac(&key, part),
+        );
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed}, Signature={}",
+            self.access_key,
+            hex(&hmac(&key, &to_sign))
+        );
+        added.push(("authorization", authorization));
+        added
+    }
+}
+
+pub(crate) fn payload_hash(payload: &[u8]) -> String {
+    hex(&Sha256::digest(payload))
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+// the date and the timestamp of sigv4, such as 20240101 and 20240101T000000Z
+fn amz_date(now: SystemTime) -> (String, String) {
+    let secs = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let (days, rem) = (secs / 86400, secs % 86400);
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    let date = format!("{year:04}{month:02}{day:02}");
+    let time = format!(
+        "{date}T{:02}{:02}{:02}Z",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    );
+    (date, time)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn example() -> Credentials {
+        Credentials::new(
+            "AKIDEXAMPLE",
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            None,
+        )
+    }
+
+    // get-vanilla of the aws sigv4 test suite, signed on 2015-08-30 at 12:36:00
+    #[test]
+    fn signs_the_get_vanilla_vector() {
+        let request = ("GET", "example.amazonaws.com", "/");
+        let empty = payload_hash(b"");
+        let headers = example().sign_at(
+            at(1440938160),
+            "us-east-1",
+            "service",
+            request,
+            vec![],
+            &empty,
+        );
+        assert_eq!(
+            headers,
+            [
+                ("x-amz-date", "20150830T123600Z".to_string()),
+                (
+                    "authorization",
+                    "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+                     SignedHeaders=host;x-amz-date, \
+                     Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    // get-vanilla-query-order-key-case of the aws sigv4 test suite
+    #[test]
+    fn signs_the_query_sorted() {
+        let request = (
+            "GET",
+            "example.amazonaws.com",
+            "/?Param2=value2&Param1=value1",
+        );
+        let empty = payload_hash(b"");
+        let headers = example().sign_at(
+            at(1440938160),
+            "us-east-1",
+            "service",
+            request,
+            vec![],
+            &empty,
+        );
+        assert!(
+            headers[1].1.ends_with(
+                "Signature=b97d918cfa904a5beff61c982a1b6f458b799221646efd99d3219ec94cdf2500"
+            ),
+            "{headers:?}"
+        );
+    }
+
+    // the signing key example of the aws sigv4 documentation
+    #[test]
+    fn derives_the_documented_signing_key() {
+        let key = ["us-east-1", "iam", "aws4_request"].iter().fold(
+            hmac(b"AWS4wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "20120215"),
+            |key, part| hmac(&key, part),
+        );
+        assert_eq!(
+            hex(&key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn signs_the_session_token() {
+        let credentials = Credentials::new("AKIDEXAMPLE", "secret", Some("token"));
+        let request = ("GET", "example.amazonaws.com", "/");
+        let headers = credentials.sign_at(at(0), "us-east-1", "s3", request, vec![], "");
+        assert_eq!(headers[1], ("x-amz-security-token", "token".to_string()));
+        assert!(headers[2]
+            .1
+            .contains("SignedHeaders=host;x-amz-date;x-amz-security-token,"));
+    }
+
+    #[test]
+    fn hashes_the_empty_payload() {
+        assert_eq!(
+            payload_hash(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn formats_the_epoch() {
+        let date = amz_date(at(0));
+        assert_eq!(date, ("19700101".into(), "19700101T000000Z".into()));
+    }
+
+    #[test]
+    fn formats_leap_days() {
+        assert_eq!(amz_date(at(1709209845)).1, "20240229T123045Z");
+        // divisible by 400, a leap year despite the century
+        assert_eq!(amz_date(at(951782400)).1, "20000229T000000Z");
+        // divisible by 100, no leap year
+        assert_eq!(amz_date(at(4107542399)).1, "21000228T235959Z");
+        assert_eq!(amz_date(at(4107542400)).1, "21000301T000000Z");
+    }
+
+    #[test]
+    fn formats_month_and_year_boundaries() {
+        assert_eq!(amz_date(at(1709251199)).1, "20240229T235959Z");
+        assert_eq!(amz_date(at(1709251200)).1, "20240301T000000Z");
+        assert_eq!(amz_date(at(1704067199)).1, "20231231T235959Z");
+        assert_eq!(amz_date(at(1704067200)).1, "20240101T000000Z");
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use tokio::fs;
+
+use crate::backup_path;
+
+// the directory a staged download is written to, beside the local path so its files are
+// renamed rather than copied into place, such as .nvim.staging for nvim
+pub(crate) fn staging_path(local_path: &Path) -> PathBuf {
+    match local_path.file_name() {
+        Some(name) => {
+            let name = format!(".{}.staging", name.to_string_lossy());
+            local_path.with_file_name(name)
+        }
+        None => local_path.join(".staging"),
+    }
+}
+
+// moves the staged files, as (staged, destination) pairs, into place, the whole tree with a
+// single rename when there is nothing at the local path yet, otherwise file by file with
+// the files replaced put aside, so all of them are put back when one can't be moved, and
+// kept as backups once all are when `backup` is set
+pub(crate) async fn commit(
+    staging: &Path,
+    local_path: &Path,
+    moves: Vec<(PathBuf, PathBuf)>,
+    backup: bool,
+) -> io::Result<()> {
+    if moves.is_empty() {
+        return Ok(());
+    }
+    // files mapped elsewhere are staged apart from the tree
+    let below = moves.iter().all(|(_, dst)| {
+        dst.strip_prefix(local_path)
+            .is_ok_and(|rest| !rest.as_os_str().is_empty())
+    });
+    if below && fs::symlink_metadata(local_path).await.is_err() {
+        if let Some(dir) = local_path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+        {
+            fs::create_dir_all(dir).await?;
+        }
+        return fs::rename(staging, local_path).await;
+    }
+    let mut moved = vec![];
+    for (staged, dst) in &moves {
+        if let Err(err) = replace(staged, dst, backup, &mut moved).await {
+            for (dst, backup) in moved.into_iter().rev() {
+                match backup {
+                    Some(backup) => fs::rename(backup, dst).await.ok(),
+                    None => fs::remove_file(dst).await.ok(),
+                };
+            }
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+async fn replace(
+    staged: &Path,
+    dst: &Path,
+    keep: bool,
+    moved: &mut Vec<(PathBuf, Option<PathBuf>)>,
+) -> io::Result<()> {
+    if let Some(dir) = dst.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).await?;
+    }
+    // the replaced file waits in the staging directory until the run is over, unless it
+    // is kept
+    let backup = match fs::symlink_metadata(dst).await {
+        Ok(meta) if meta.is_dir() => {
+            let err = format!("{} is a directory", dst.display());
+            return Err(io::Error::other(err));
+        }
+        Ok(_) if keep => {
+            let backup = backup_path(dst);
+            fs::rename(dst, &backup).await?;
+            Some(backup)
+        }
+        Ok(_) => {
+            let backup = staged.with_file_name(format!(
+                "{}.replaced",
+                staged.file_name().unwrap_or_default().to_string_lossy()
+            ));
+            fs::rename(dst, &backup).await?;
+            Some(backup)
+        }
+        Err(_) => None,
+    };
+    if let Err(err) = fs::rename(staged, dst).await {
+        if let Some(backup) = backup {
+            fs::rename(backup, dst).await.ok();
+        }
+        return Err(err);
+    }
+    moved.push((dst.into(), backup));
+    Ok(())
+}
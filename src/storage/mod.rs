@@ -0,0 +1,163 @@
+use std::{
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{ready, Context, Poll},
+};
+
+use anyhow::Result;
+use bytes::Bytes;
+use futures_util::{
+    stream::{unfold, BoxStream},
+    StreamExt,
+};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::mpsc::channel,
+    task::JoinHandle,
+};
+use tokio_util::sync::PollSender;
+
+pub use async_trait::async_trait;
+#[cfg(feature = "s3")]
+pub use s3::S3;
+
+#[cfg(feature = "s3")]
+mod s3;
+
+/// What is known of a stored file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    pub size: u64,
+}
+
+/// Where the files are written, implement it to keep them elsewhere than on the local
+/// filesystem, such as in object storage, a virtual filesystem or in memory for tests
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Stores the file at `path`, replacing the one stored before, the content ends with an
+    /// error when the download of the file is given up on, it should then be discarded
+    async fn put(&self, path: &Path, content: BoxStream<'static, io::Result<Bytes>>) -> Result<()>;
+
+    /// Whether a file is stored at `path`
+    async fn exists(&self, path: &Path) -> Result<bool> {
+        Ok(self.metadata(path).await?.is_some())
+    }
+
+    /// The file stored at `path`, if any
+    async fn metadata(&self, path: &Path) -> Result<Option<Metadata>>;
+}
+
+/// The local filesystem, the files are written to it unless another storage is given
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LocalStorage;
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(
+        &self,
+        path: &Path,
+        mut content: BoxStream<'static, io::Result<Bytes>>,
+    ) -> Result<()> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).await?;
+        }
+        let mut file = File::create(path).await?;
+        while let Some(chunk) = content.next().await {
+            match chunk {
+                Ok(chunk) => file.write_all(&chunk).await?,
+                Err(err) => {
+                    drop(file);
+                    fs::remove_file(path).await.ok();
+                    return Err(err.into());
+                }
+            }
+        }
+        file.flush().await?;
+        Ok(())
+    }
+
+    async fn metadata(&self, path: &Path) -> Result<Option<Metadata>> {
+        // a link is stored as itself, wherever it points
+        match fs::symlink_metadata(path).await {
+            Ok(meta) if !meta.is_dir() => Ok(Some(Metadata { size: meta.len() })),
+            Ok(_) => Ok(None),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+// the writer a file is fetched into, its chunks are handed on to `put` as they are written
+pub(crate) fn writer(storage: Arc<dyn Storage>, path: &Path) -> Pin<Box<dyn AsyncWrite + Send>> {
+    let (tx, rx) = channel::<Bytes>(8);
+    let done = Arc::new(AtomicBool::new(false));
+    let finished = done.clone();
+    // a writer dropped before it is shut down cuts the content off with an error
+    let content = unfold(Some(rx), move |rx| {
+        let finished = finished.clone();
+        async move {
+            let mut rx = rx?;
+            match rx.recv().await {
+                Some(chunk) => Some((Ok(chunk), Some(rx))),
+                None if finished.load(Ordering::Acquire) => None,
+                None => Some((Err(io::Error::other("The download was cut off")), None)),
+            }
+        }
+    });
+    let path = PathBuf::from(path);
+    let put = tokio::spawn(async move { storage.put(&path, content.boxed()).await });
+    Box::pin(StorageWriter {
+        tx: PollSender::new(tx),
+        done,
+        put,
+    })
+}
+
+struct StorageWriter {
+    tx: PollSender<Bytes>,
+    done: Arc<AtomicBool>,
+    put: JoinHandle<Result<()>>,
+}
+
+impl StorageWriter {
+    // the storage stopped reading, its error explains why
+    fn poll_put(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        let put = ready!(Pin::new(&mut self.put).poll(cx));
+        Poll::Ready(
+            put.map_err(io::Error::other)
+                .and_then(|put| put.map_err(io::Error::other)),
+        )
+    }
+}
+
+impl AsyncWrite for StorageWriter {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if ready!(this.tx.poll_reserve(cx)).is_err() {
+            ready!(this.poll_put(cx))?;
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        match this.tx.send_item(Bytes::copy_from_slice(buf)) {
+            Ok(()) => Poll::Ready(Ok(buf.len())),
+            Err(_) => Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.done.store(true, Ordering::Release);
+        this.tx.close();
+        this.poll_put(cx)
+    }
+}
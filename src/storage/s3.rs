@@ -0,0 +1,403 @@
+use std::{
+    io,
+    path::{Component, Path},
+};
+
+use anyhow::{anyhow, Result};
+use bytes::{Bytes, BytesMut};
+use futures_util::{stream::BoxStream, StreamExt};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::{
+    header::{CONTENT_LENGTH, ETAG},
+    Client, Method, RequestBuilder, Response, StatusCode, Url,
+};
+
+use super::{async_trait, Metadata, Storage};
+use crate::sigv4::{payload_hash, Credentials};
+
+// the characters sigv4 leaves unescaped in a key
+const KEY: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// An S3 compatible bucket the files are uploaded to, each one with a single signed PUT, or
+/// in parts of 8 MiB once larger, keys are the prefix joined with the paths the files would
+/// have on disk
+pub struct S3 {
+    client: Client,
+    endpoint: String,
+    region: String,
+    bucket: String,
+    prefix: String,
+    credentials: Option<Credentials>,
+}
+
+// a multipart upload under way, with the etags of the parts uploaded so far
+struct Upload {
+    id: String,
+    etags: Vec<String>,
+}
+
+impl S3 {
+    const SERVICE: &'static str = "s3";
+    // S3 wants parts of at least 5 MiB but the last, a file up to one part is sent whole
+    const PART_SIZE: usize = 8 * 1024 * 1024;
+
+    /// Credentials are taken from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+    /// `AWS_SESSION_TOKEN` unless given with [`S3::credentials`]
+    pub fn new(bucket: &str, region: &str) -> Self {
+        Self {
+            client: Client::new(),
+            endpoint: format!("https://s3.{region}.amazonaws.com"),
+            region: region.into(),
+            bucket: bucket.into(),
+            prefix: String::new(),
+            credentials: Credentials::from_env(),
+        }
+    }
+
+    /// Another S3 compatible service such as MinIO or Cloudflare R2, buckets are addressed
+    /// in the path, such as `http://localhost:9000/<bucket>/<key>`
+    pub fn endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoint = endpoint.trim_end_matches('/').into();
+        self
+    }
+
+    /// Keep the files below `prefix`, such as `mirrors/dotfiles`
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.trim_matches('/').into();
+        self
+    }
+
+    pub fn credentials(
+        mut self,
+        access_key: &str,
+        secret_key: &str,
+        session_token: Option<&str>,
+    ) -> Self {
+        self.credentials = Some(Credentials::new(access_key, secret_key, session_token));
+        self
+    }
+
+    /// The client the uploads are sent with, such as one sharing a connection pool
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    fn key(&self, path: &Path) -> String {
+        let parts = path
+            .components()
+            .filter_map(|part| match part {
+                Component::Normal(part) => Some(part.to_string_lossy()),
+                _ => None,
+            })
+            .map(|part| utf8_percent_encode(&part, KEY).to_string());
+        let prefix = (!self.prefix.is_empty()).then(|| {
+            self.prefix
+                .split('/')
+                .map(|part| utf8_percent_encode(part, KEY).to_string())
+                .collect::<Vec<_>>()
+                .join("/")
+        });
+        prefix
+            .into_iter()
+            .chain(parts)
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    // `query` is made of encoded pairs, such as partNumber=1&uploadId=abc
+    fn request(
+        &self,
+        method: Method,
+        path: &Path,
+        query: &str,
+        body: &[u8],
+    ) -> Result<RequestBuilder> {
+        let mut path = format!(
+            "/{}/{}",
+            utf8_percent_encode(&self.bucket, KEY),
+            self.key(path)
+        );
+        if !query.is_empty() {
+            path = format!("{path}?{query}");
+        }
+        let url = Url::parse(&format!("{}{path}", self.endpoint))?;
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            (Some(host), None) => host.into(),
+            (None, _) => return Err(anyhow!("{} has no host", self.endpoint)),
+        };
+        let req = self.client.request(method.clone(), url);
+        let Some(credentials) = &self.credentials else {
+            return Ok(req);
+        };
+        let hash = payload_hash(body);
+        let headers = credentials.sign(
+            &self.region,
+            Self::SERVICE,
+            method.as_str(),
+            &host,
+            &path,
+            vec![("x-amz-content-sha256", hash.clone())],
+            &hash,
+        );
+        let req = req.header("x-amz-content-sha256", hash);
+        Ok(headers
+            .into_iter()
+            .fold(req, |req, (name, value)| req.header(name, value)))
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        path: &Path,
+        query: &str,
+        body: Bytes,
+    ) -> Result<Response> {
+        let req = self.request(method, path, query, &body)?;
+        Ok(req.body(body).send().await?.error_for_status()?)
+    }
+
+    async fn create_upload(&self, path: &Path) -> Result<Upload> {
+        let res = self
+            .send(Method::POST, path, "uploads", Bytes::new())
+            .await?;
+        let body = res.text().await?;
+        let id = body
+            .split_once("<UploadId>")
+            .and_then(|(_, rest)| rest.split_once("</UploadId>"))
+            .map(|(id, _)| id.to_string())
+            .ok_or_else(|| anyhow!("No upload id in {body}"))?;
+        Ok(Upload { id, etags: vec![] })
+    }
+
+    async fn upload_part(&self, path: &Path, upload: &mut Upload, part: Bytes) -> Result<()> {
+        let query = format!(
+            "partNumber={}&uploadId={}",
+            upload.etags.len() + 1,
+            utf8_percent_encode(&upload.id, KEY)
+        );
+        let res = self.send(Method::PUT, path, &query, part).await?;
+        let etag = res
+            .headers()
+            .get(ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .ok_or_else(|| anyhow!("No etag for part {}", upload.etags.len() + 1))?;
+        upload.etags.push(etag.into());
+        Ok(())
+    }
+
+    async fn complete_upload(&self, path: &Path, upload: &Upload) -> Result<()> {
+        let parts: String = upload
+            .etags
+            .iter()
+            .enumerate()
+            .map(|(i, etag)| {
+                let number = i + 1;
+                format!("<Part><PartNumber>{number}</PartNumber><ETag>{etag}</ETag></Part>")
+            })
+            .collect();
+        let body = format!("<CompleteMultipartUpload>{parts}</CompleteMultipartUpload>");
+        let query = format!("uploadId={}", utf8_percent_encode(&upload.id, KEY));
+        let res = self.send(Method::POST, path, &query, body.into()).await?;
+        // a failure after the upload started is answered with 200 and an error body
+        let body = res.text().await?;
+        match body.contains("<Error>") {
+            true => Err(anyhow!("The upload wasn't completed: {body}")),
+            false => Ok(()),
+        }
+    }
+
+    // the parts uploaded would otherwise be kept, and billed, until the upload is aborted
+    async fn abort_upload(&self, path: &Path, upload: &Upload) {
+        let query = format!("uploadId={}", utf8_percent_encode(&upload.id, KEY));
+        self.send(Method::DELETE, path, &query, Bytes::new())
+            .await
+            .ok();
+    }
+
+    // the content in parts as it streams in, so no more than a part is held
+    async fn put_parts(
+        &self,
+        path: &Path,
+        upload: &mut Upload,
+        mut part: BytesMut,
+        content: &mut BoxStream<'static, io::Result<Bytes>>,
+    ) -> Result<()> {
+        let mut done = false;
+        loop {
+            while !done && part.len() < Self::PART_SIZE {
+                match content.next().await {
+                    Some(chunk) => part.extend_from_slice(&chunk?),
+                    None => done = true,
+                }
+            }
+            if !part.is_empty() {
+                let size = part.len().min(Self::PART_SIZE);
+                self.upload_part(path, upload, part.split_to(size).freeze())
+                    .await?;
+            }
+            if done && part.is_empty() {
+                return self.complete_upload(path, upload).await;
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for S3 {
+    // S3 wants the length of a body before it is sent, the content is gathered up to a
+    // part and sent whole, a larger file is uploaded part by part
+    async fn put(
+        &self,
+        path: &Path,
+        mut content: BoxStream<'static, io::Result<Bytes>>,
+    ) -> Result<()> {
+        let mut body = BytesMut::new();
+        while body.len() < Self::PART_SIZE {
+            match content.next().await {
+                Some(chunk) => body.extend_from_slice(&chunk?),
+                None => {
+                    self.send(Method::PUT, path, "", body.freeze()).await?;
+                    return Ok(());
+                }
+            }
+        }
+        let mut upload = self.create_upload(path).await?;
+        let res = self.put_parts(path, &mut upload, body, &mut content).await;
+        if res.is_err() {
+            self.abort_upload(path, &upload).await;
+        }
+        res
+    }
+
+    async fn metadata(&self, path: &Path) -> Result<Option<Metadata>> {
+        let res = self.request(Method::HEAD, path, "", &[])?.send().await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let res = res.error_for_status()?;
+        let size = res
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok()?.parse().ok())
+            .unwrap_or_default();
+        Ok(Some(Metadata { size }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::stream;
+
+    use super::*;
+    use crate::stub::{Response, Stub};
+
+    fn s3(stub: &Stub) -> S3 {
+        S3::new("bucket", "us-east-1")
+            .endpoint(&stub.url)
+            .credentials("a", "b", None)
+    }
+
+    // the content in chunks of a MiB
+    fn content(size: usize) -> BoxStream<'static, io::Result<Bytes>> {
+        let chunks = (0..size)
+            .step_by(1024 * 1024)
+            .map(move |at| Ok(Bytes::from(vec![b'a'; (size - at).min(1024 * 1024)])));
+        stream::iter(chunks.collect::<Vec<_>>()).boxed()
+    }
+
+    async fn multipart() -> Stub {
+        Stub::start(|req| match (req.method.as_str(), req.path.as_str()) {
+            ("POST", "/bucket/big.bin?uploads") => Response::ok(
+                "<InitiateMultipartUploadResult><UploadId>id/1</UploadId></InitiateMultipartUploadResult>",
+            ),
+            ("PUT", path) if path.contains("partNumber=") => {
+                let number = path.split_once("partNumber=").unwrap().1.split('&').next();
+                Response::ok("").header("etag", format!("\"{}\"", number.unwrap()))
+            }
+            ("PUT", "/bucket/broken.bin") => Response::new(500),
+            _ => Response::ok(""),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn puts_a_small_file_whole() {
+        let stub = multipart().await;
+        s3(&stub)
+            .put(Path::new("small.bin"), content(1024))
+            .await
+            .unwrap();
+        let requests = stub.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "PUT");
+        assert_eq!(requests[0].path, "/bucket/small.bin");
+        assert_eq!(requests[0].body.len(), 1024);
+        assert!(requests[0].header("authorization").is_some());
+    }
+
+    #[tokio::test]
+    async fn uploads_a_large_file_in_parts() {
+        let stub = multipart().await;
+        let size = 2 * S3::PART_SIZE + 1;
+        s3(&stub)
+            .put(Path::new("big.bin"), content(size))
+            .await
+            .unwrap();
+        let requests = stub.requests();
+        let calls: Vec<_> = requests
+            .iter()
+            .map(|req| format!("{} {}", req.method, req.path))
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                "POST /bucket/big.bin?uploads",
+                "PUT /bucket/big.bin?partNumber=1&uploadId=id%2F1",
+                "PUT /bucket/big.bin?partNumber=2&uploadId=id%2F1",
+                "PUT /bucket/big.bin?partNumber=3&uploadId=id%2F1",
+                "POST /bucket/big.bin?uploadId=id%2F1",
+            ]
+        );
+        let sizes: Vec<_> = requests[1..4].iter().map(|req| req.body.len()).collect();
+        assert_eq!(sizes, [S3::PART_SIZE, S3::PART_SIZE, 1]);
+        let complete = String::from_utf8(requests[4].body.clone()).unwrap();
+        assert_eq!(
+            complete,
+            "<CompleteMultipartUpload>\
+             <Part><PartNumber>1</PartNumber><ETag>\"1\"</ETag></Part>\
+             <Part><PartNumber>2</PartNumber><ETag>\"2\"</ETag></Part>\
+             <Part><PartNumber>3</PartNumber><ETag>\"3\"</ETag></Part>\
+             </CompleteMultipartUpload>"
+        );
+    }
+
+    #[tokio::test]
+    async fn aborts_an_upload_cut_off() {
+        let stub = multipart().await;
+        let broken = stream::iter([Ok(Bytes::from(vec![b'a'; S3::PART_SIZE]))])
+            .chain(stream::iter([Err(io::Error::other("cut off"))]));
+        let err = s3(&stub)
+            .put(Path::new("big.bin"), broken.boxed())
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "cut off");
+        let last = stub.requests().pop().unwrap();
+        assert_eq!(
+            format!("{} {}", last.method, last.path),
+            "DELETE /bucket/big.bin?uploadId=id%2F1"
+        );
+    }
+
+    #[tokio::test]
+    async fn fails_a_small_file_refused() {
+        let stub = multipart().await;
+        let res = s3(&stub).put(Path::new("broken.bin"), content(1)).await;
+        assert!(res.is_err());
+    }
+}
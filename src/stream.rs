@@ -0,0 +1,49 @@
+use std::io::{self, Read};
+
+use anyhow::Result;
+use bytes::Bytes;
+use reqwest::Response;
+use tokio::sync::mpsc::{channel, Receiver};
+
+// reads the chunks of a response body handed over from the async side
+pub(crate) struct ChunkReader {
+    rx: Receiver<Bytes>,
+    chunk: Bytes,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => self.chunk = chunk,
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk.split_to(n));
+        Ok(n)
+    }
+}
+
+// hands the body to `read` on a blocking thread while it downloads, so it is consumed
+// without ever being held whole, memory stays bounded by a few chunks
+pub(crate) async fn read_blocking<T, F>(mut res: Response, read: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(ChunkReader) -> Result<T> + Send + 'static,
+{
+    let (tx, rx) = channel(8);
+    let reader = ChunkReader {
+        rx,
+        chunk: Bytes::new(),
+    };
+    let task = tokio::task::spawn_blocking(move || read(reader));
+    while let Some(chunk) = res.chunk().await? {
+        // the reader stopped early on an error it reports below
+        if tx.send(chunk).await.is_err() {
+            break;
+        }
+    }
+    drop(tx);
+    task.await?
+}
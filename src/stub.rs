@@ -0,0 +1,267 @@
+// a local http server answering the requests of a test, and a provider listing a tree
+// whose files it serves
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    blob::content_sha,
+    provider::{async_trait, Client, Provider, RequestBuilder},
+    Node, Remote,
+};
+
+#[derive(Clone, Debug)]
+pub(crate) struct Request {
+    // only the uploads of the s3 storage are told apart by them
+    #[cfg_attr(not(feature = "s3"), allow(dead_code))]
+    pub(crate) method: String,
+    pub(crate) path: String,
+    headers: HashMap<String, String>,
+    #[cfg_attr(not(feature = "s3"), allow(dead_code))]
+    pub(crate) body: Vec<u8>,
+}
+
+impl Request {
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+}
+
+pub(crate) struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    delay: Duration,
+    // the body is followed by as many bytes again that never arrive
+    stalled: bool,
+}
+
+impl Response {
+    pub(crate) fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: vec![],
+            body: vec![],
+            delay: Duration::ZERO,
+            stalled: false,
+        }
+    }
+
+    pub(crate) fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self::new(200).body(body)
+    }
+
+    pub(crate) fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    pub(crate) fn header(mut self, name: &str, value: impl ToString) -> Self {
+        self.headers.push((name.into(), value.to_string()));
+        self
+    }
+
+    // answers only once `delay` passed
+    pub(crate) fn after(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    // sends the start of the body, then hangs until the client gives up
+    pub(crate) fn stalled(mut self) -> Self {
+        self.stalled = true;
+        self
+    }
+}
+
+type Handler = Arc<dyn Fn(&Request) -> Response + Send + Sync>;
+
+pub(crate) struct Stub {
+    pub(crate) url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl Stub {
+    // serves every connection with `handler`, one request each
+    pub(crate) async fn start(
+        handler: impl Fn(&Request) -> Response + Send + Sync + 'static,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::<Mutex<Vec<Request>>>::default();
+        let handler: Handler = Arc::new(handler);
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (handler, seen) = (handler.clone(), seen.clone());
+                tokio::spawn(async move { serve(stream, handler, seen).await.ok() });
+            }
+        });
+        Self { url, requests }
+    }
+
+    pub(crate) fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn serve(stream: TcpStream, handler: Handler, seen: Arc<Mutex<Vec<Request>>>) -> Result<()> {
+    let mut stream = BufReader::new(stream);
+    let mut line = String::new();
+    stream.read_line(&mut line).await?;
+    let mut start = line.split_whitespace().map(str::to_string);
+    let (method, path) = (
+        start.next().unwrap_or_default(),
+        start.next().unwrap_or_default(),
+    );
+    let mut headers = HashMap::new();
+    loop {
+        line.clear();
+        stream.read_line(&mut line).await?;
+        match line.trim_end().split_once(':') {
+            Some((name, value)) => headers.insert(name.to_lowercase(), value.trim().to_string()),
+            None => break,
+        };
+    }
+    let length = headers
+        .get("content-length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).await?;
+    let req = Request {
+        method,
+        path,
+        headers,
+        body,
+    };
+    let res = handler(&req);
+    seen.lock().unwrap().push(req);
+    tokio::time::sleep(res.delay).await;
+    let length = res.body.len() * if res.stalled { 2 } else { 1 };
+    let mut head = format!(
+        "HTTP/1.1 {} Stub\r\ncontent-length: {length}\r\nconnection: close\r\n",
+        res.status,
+    );
+    for (name, value) in &res.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    let stream = stream.get_mut();
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&res.body).await?;
+    if res.stalled {
+        stream.flush().await?;
+        std::future::pending::<()>().await;
+    }
+    stream.shutdown().await?;
+    Ok(())
+}
+
+// lists the files as blobs with their sizes and shas, and fetches them from the stub
+pub(crate) struct StubProvider {
+    url: String,
+    files: Vec<(String, Vec<u8>)>,
+    commit: Option<String>,
+    modules: Vec<(String, String)>,
+}
+
+impl StubProvider {
+    pub(crate) fn new(stub: &Stub, files: &[(&str, &[u8])]) -> Self {
+        Self {
+            url: stub.url.clone(),
+            files: files
+                .iter()
+                .map(|(path, content)| (path.to_string(), content.to_vec()))
+                .collect(),
+            commit: None,
+            modules: vec![],
+        }
+    }
+
+    // the sha the ref resolves to
+    pub(crate) fn commit(mut self, sha: &str) -> Self {
+        self.commit = Some(sha.into());
+        self
+    }
+
+    // a submodule at `path` pinned to `sha`, where the tree is listed without it
+    pub(crate) fn submodule(mut self, path: &str, sha: &str) -> Self {
+        self.modules.push((path.into(), sha.into()));
+        self
+    }
+}
+
+#[async_trait]
+impl Provider for StubProvider {
+    async fn tree(&self, _client: &Client, remote: &Remote) -> Result<Vec<Node>> {
+        let files = self.files.iter().map(|(path, content)| Node {
+            path: path.clone(),
+            kind: "blob".into(),
+            size: Some(content.len() as u64),
+            sha: Some(content_sha(content)),
+            mode: Some("100644".into()),
+        });
+        let modules = self
+            .modules
+            .iter()
+            .filter(|(_, sha)| remote.branch != *sha)
+            .map(|(path, sha)| Node {
+                path: path.clone(),
+                kind: "commit".into(),
+                size: None,
+                sha: Some(sha.clone()),
+                mode: Some("160000".into()),
+            });
+        Ok(files.chain(modules).collect())
+    }
+
+    async fn commit(&self, _client: &Client, _remote: &Remote) -> Result<Option<String>> {
+        Ok(self.commit.clone())
+    }
+
+    fn raw(&self, client: &Client, _remote: &Remote, path: &str) -> RequestBuilder {
+        client.get(format!("{}/{path}", self.url))
+    }
+}
+
+// a directory of its own below the system temp dir, removed once dropped
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub(crate) fn new() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "gitload-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        );
+        let dir = std::env::temp_dir().join(name);
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.0).ok();
+    }
+}
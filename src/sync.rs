@@ -0,0 +1,49 @@
+use std::{
+    collections::BTreeSet,
+    io,
+    path::{Path, PathBuf},
+};
+
+use tokio::fs;
+
+// the files below `dir`, symlinks are taken as files and not followed, the paths in
+// `skipped` are left out with everything below them
+pub(crate) async fn walk(dir: &Path, skipped: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries = match fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if skipped.contains(&path) {
+                continue;
+            }
+            match entry.file_type().await?.is_dir() {
+                true => pending.push(path),
+                false => files.push(path),
+            }
+        }
+    }
+    Ok(files)
+}
+
+// the directories below `root` left empty by the removed files are removed too, deepest
+// first, the others are kept
+pub(crate) async fn remove_emptied(root: &Path, removed: &[PathBuf]) {
+    let dirs: BTreeSet<_> = removed
+        .iter()
+        .flat_map(|path| {
+            path.ancestors()
+                .skip(1)
+                .take_while(|dir| *dir != root && dir.starts_with(root))
+        })
+        .map(|dir| (std::cmp::Reverse(dir.components().count()), dir))
+        .collect();
+    for (_, dir) in dirs {
+        fs::remove_dir(dir).await.ok();
+    }
+}